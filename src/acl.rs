@@ -0,0 +1,230 @@
+//! Permissions
+
+use irc::client::prelude::Config;
+
+use std::fmt;
+
+///User's role. Roles are ordered from least to most privileged.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Role {
+    ///Bot doesn't respond to such users.
+    Ignored,
+    User,
+    Trusted,
+    Owner
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.write_str(match *self {
+            Role::Ignored => "ignored",
+            Role::User    => "user",
+            Role::Trusted => "trusted",
+            Role::Owner   => "owner",
+        })
+    }
+}
+
+///Matches user either by hostmask or by NickServ account.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Matcher {
+    ///Hostmask pattern `nick!user@host` with wildcards `*` and `?`.
+    Mask(String),
+    ///NickServ account name.
+    Account(String)
+}
+
+impl Matcher {
+    ///Parses matcher from config entry.
+    ///
+    ///Entry with `!` or `@` is treated as hostmask, otherwise as account.
+    pub fn parse(entry: &str) -> Matcher {
+        let entry = entry.trim();
+        if entry.contains('!') || entry.contains('@') {
+            Matcher::Mask(entry.to_lowercase())
+        }
+        else {
+            Matcher::Account(entry.to_lowercase())
+        }
+    }
+
+    #[inline]
+    pub fn is_match(&self, prefix: &str, account: Option<&str>) -> bool {
+        match *self {
+            Matcher::Mask(ref mask)    => mask_match(mask, &prefix.to_lowercase()),
+            Matcher::Account(ref name) => account.map_or(false, |account| account.to_lowercase() == *name),
+        }
+    }
+}
+
+impl fmt::Display for Matcher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Matcher::Mask(ref mask)    => write!(f, "{}", mask),
+            Matcher::Account(ref name) => write!(f, "${}", name),
+        }
+    }
+}
+
+///Checks whether text matches glob-like mask with `*` and `?`.
+pub fn mask_match(mask: &str, text: &str) -> bool {
+    let mask: Vec<char> = mask.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut mask_idx, mut text_idx) = (0, 0);
+    //Position of last `*` in mask and position in text it matched against.
+    let mut star: Option<(usize, usize)> = None;
+
+    while text_idx < text.len() {
+        if mask_idx < mask.len() && (mask[mask_idx] == '?' || mask[mask_idx] == text[text_idx]) {
+            mask_idx += 1;
+            text_idx += 1;
+        }
+        else if mask_idx < mask.len() && mask[mask_idx] == '*' {
+            star = Some((mask_idx, text_idx));
+            mask_idx += 1;
+        }
+        else if let Some((star_mask, star_text)) = star {
+            mask_idx = star_mask + 1;
+            text_idx = star_text + 1;
+            star = Some((star_mask, star_text + 1));
+        }
+        else {
+            return false;
+        }
+    }
+
+    mask[mask_idx..].iter().all(|ch| *ch == '*')
+}
+
+///Access control list.
+pub struct Acl {
+    owners: Vec<Matcher>,
+    trusted: Vec<Matcher>,
+    ignored: Vec<Matcher>
+}
+
+impl Acl {
+    #[inline(always)]
+    pub fn new() -> Acl {
+        Acl {
+            owners: Vec::new(),
+            trusted: Vec::new(),
+            ignored: Vec::new()
+        }
+    }
+
+    ///Creates ACL from config.
+    ///
+    ///Owners are taken from `owners`, while trusted and ignored from
+    ///space separated options `trusted` and `ignored`.
+    pub fn from_config(config: &Config) -> Acl {
+        let option = |name: &str| -> Vec<Matcher> {
+            config.options.as_ref()
+                          .and_then(|options| options.get(name))
+                          .map_or(Vec::new(), |entries| entries.split_whitespace().map(Matcher::parse).collect())
+        };
+
+        Acl {
+            owners: config.owners.as_ref().map_or(Vec::new(), |owners| owners.iter().map(|owner| Matcher::parse(owner)).collect()),
+            trusted: option("trusted"),
+            ignored: option("ignored")
+        }
+    }
+
+    #[cfg(test)]
+    #[inline(always)]
+    pub fn add(&mut self, role: Role, matcher: Matcher) {
+        match role {
+            Role::Owner   => self.owners.push(matcher),
+            Role::Trusted => self.trusted.push(matcher),
+            Role::Ignored => self.ignored.push(matcher),
+            Role::User    => (),
+        }
+    }
+
+    fn role_by<F: Fn(&Matcher) -> bool>(&self, is_match: F) -> Role {
+        let is_match = |matchers: &Vec<Matcher>| matchers.iter().any(|matcher| is_match(matcher));
+
+        if is_match(&self.owners) {
+            Role::Owner
+        }
+        else if is_match(&self.trusted) {
+            Role::Trusted
+        }
+        else if is_match(&self.ignored) {
+            Role::Ignored
+        }
+        else {
+            Role::User
+        }
+    }
+
+    #[inline]
+    ///Returns role of user with full prefix `nick!user@host` and optional NickServ account.
+    pub fn role(&self, prefix: &str, account: Option<&str>) -> Role {
+        self.role_by(|matcher| matcher.is_match(prefix, account))
+    }
+
+    ///Returns role of user who is confirmed to be logged in as account.
+    ///
+    ///Hostmasks are taken into account only when user is logged in as own nickname,
+    ///so that someone logged in as other account cannot gain privileges by nickname.
+    pub fn verified_role(&self, prefix: &str, account: &str) -> Role {
+        let nickname = &prefix[..prefix.find('!').unwrap_or(prefix.len())];
+
+        if nickname.to_lowercase() == account.to_lowercase() {
+            self.role(prefix, Some(account))
+        }
+        else {
+            self.role_by(|matcher| match *matcher {
+                Matcher::Account(_) => matcher.is_match(prefix, Some(account)),
+                Matcher::Mask(_)    => false,
+            })
+        }
+    }
+}
+
+impl fmt::Display for Acl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Acl(owners={}, trusted={}, ignored={})", self.owners.len(), self.trusted.len(), self.ignored.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_match() {
+        assert!(mask_match("douman!*@*", "douman!douman@irc.net"));
+        assert!(mask_match("*!*@irc.net", "douman!douman@irc.net"));
+        assert!(mask_match("d?uman!*@*.net", "douman!douman@irc.net"));
+        assert!(mask_match("*", ""));
+
+        assert!(!mask_match("douman!*@*", "douman_x!douman@irc.net"));
+        assert!(!mask_match("*!*@irc.net", "douman!douman@evil.irc.net.com"));
+        assert!(!mask_match("douman", "douman_x"));
+    }
+
+    #[test]
+    fn test_acl_role() {
+        let mut acl = Acl::new();
+        acl.add(Role::Owner, Matcher::parse("Douman"));
+        acl.add(Role::Owner, Matcher::parse("Douman!*@douman.users.rizon"));
+        acl.add(Role::Trusted, Matcher::parse("*!*@friend.net"));
+        acl.add(Role::Ignored, Matcher::parse("*!*@*.spam"));
+
+        assert!(acl.role("Douman!douman@douman.users.rizon", None) == Role::Owner);
+        assert!(acl.role("Douman_x!douman@evil.net", None) == Role::User);
+        assert!(acl.role("Douman_x!douman@evil.net", Some("Douman")) == Role::Owner);
+        assert!(acl.role("Kuu!kuu@friend.net", None) == Role::Trusted);
+        assert!(acl.role("Bot!bot@some.spam", None) == Role::Ignored);
+        assert!(acl.role("Bot!bot@some.spam", Some("douman")) == Role::Owner);
+
+        assert!(acl.verified_role("Douman_x!douman@evil.net", "douman") == Role::Owner);
+        assert!(acl.verified_role("Douman!douman@douman.users.rizon", "Douman") == Role::Owner);
+        assert!(acl.verified_role("Douman!douman@douman.users.rizon", "evil") == Role::User);
+        assert!(acl.verified_role("Kuu!kuu@friend.net", "kuu") == Role::Trusted);
+        assert!(acl.verified_role("Kuu!kuu@friend.net", "evil") == Role::User);
+    }
+}
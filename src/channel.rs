@@ -0,0 +1,210 @@
+//! Channel state
+
+use std::fmt;
+
+use log;
+use roster;
+use sqlite;
+
+#[inline(always)]
+///Returns key of channel in map of channels.
+///
+///Channel names are case insensitive, so that server may echo them differently than in config.
+pub fn key(name: &str) -> String {
+    name.to_lowercase()
+}
+
+///Record of bot being kicked out of channel.
+pub struct Kick {
+    pub by: String,
+    pub reason: String
+}
+
+///State of channel served by bot.
+pub struct Channel {
+    pub name: String,
+    pub joined: bool,
+    pub log: log::IrcLog,
+    pub roster: roster::Roster,
+    ///Last time bot was kicked out.
+    pub kick: Option<Kick>,
+    ///Number of failed attempts to rejoin since last kick.
+    pub rejoin_attempts: u32,
+    ///Time(in seconds) when bot should try to join channel again.
+    pub rejoin_at: Option<i64>
+}
+
+impl Channel {
+    #[inline(always)]
+    ///Creates channel with its own log file.
+    pub fn new(name: &str) -> Channel {
+        Channel::with_backend(name, log::Backend::File)
+    }
+
+    ///Creates channel with log in specified store.
+    pub fn with_backend(name: &str, backend: log::Backend) -> Channel {
+        Channel {
+            name: name.to_string(),
+            joined: false,
+            log: log::IrcLog::with_store(Channel::open_store(name, backend), name),
+            roster: roster::Roster::new(),
+            kick: None,
+            rejoin_attempts: 0,
+            rejoin_at: None
+        }
+    }
+
+    #[inline]
+    ///Marks channel as joined.
+    pub fn join(&mut self) {
+        self.joined = true;
+        self.rejoin_attempts = 0;
+        self.rejoin_at = None;
+    }
+
+    #[inline]
+    ///Records that bot is kicked out of channel.
+    pub fn kicked(&mut self, by: &str, reason: &str) {
+        self.joined = false;
+        self.roster.clear();
+        self.rejoin_attempts = 0;
+        self.kick = Some(Kick {
+            by: by.to_string(),
+            reason: reason.to_string()
+        });
+    }
+
+    #[inline]
+    ///Records failed attempt to join channel.
+    ///
+    ///Returns whether bot should try again.
+    pub fn rejoin_failed(&mut self, max_attempts: u32) -> bool {
+        self.rejoin_attempts += 1;
+        self.rejoin_attempts < max_attempts
+    }
+
+    #[inline]
+    ///Returns whether scheduled rejoin is due, unless channel is joined already.
+    ///
+    ///Each scheduled rejoin is reported only once.
+    pub fn should_rejoin(&mut self, now: i64) -> bool {
+        match self.rejoin_at {
+            Some(at) if at <= now => {
+                self.rejoin_at = None;
+                !self.joined
+            },
+            _ => false,
+        }
+    }
+
+    ///Opens log store of channel.
+    ///
+    ///Log file is used if database cannot be opened.
+    pub fn open_store(name: &str, backend: log::Backend) -> Box<log::LogStore> {
+        if backend == log::Backend::Sqlite {
+            match sqlite::SqliteStore::open(sqlite::DB_PATH, name) {
+                Ok(store) => return Box::new(store),
+                Err(err)  => println!(">>>ERROR: {}", err),
+            }
+        }
+
+        Box::new(log::FileStore::open(&Channel::log_file(name)))
+    }
+
+    #[inline]
+    ///Switches log of channel to specified store.
+    pub fn set_backend(&mut self, backend: log::Backend) {
+        let store = Channel::open_store(&self.name, backend);
+        self.log.set_store(store);
+    }
+
+    ///Returns name of log file for channel i.e. `#vndis` => `vndis.log`
+    ///
+    ///Leading `#` is dropped and other chars, except for letters, digits and `_`, are percent-encoded.
+    ///Thus channels never share log file and none of them can be taken for rotated file of another one.
+    pub fn log_file(name: &str) -> String {
+        let name = if name.starts_with('#') { &name[1..] } else { name };
+        let mut result = String::new();
+
+        for ch in key(name).chars() {
+            if ch.is_alphanumeric() || ch == '_' {
+                result.push(ch);
+            }
+            else {
+                for byte in ch.to_string().bytes() {
+                    result.push_str(&format!("%{:02X}", byte));
+                }
+            }
+        }
+
+        format!("{}.log", result)
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        try!(write!(f, "{}(joined={}, users={}", self.name, self.joined, self.roster.len()));
+        if let Some(ref kick) = self.kick {
+            try!(write!(f, ", kicked by {}: {}", kick.by, kick.reason));
+        }
+        write!(f, ") {}", self.log)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std;
+
+    #[test]
+    fn test_log_file() {
+        assert!(Channel::log_file("#vndis") == "vndis.log");
+        assert!(Channel::log_file("#VNDIS") == "vndis.log");
+        assert!(key("#VNDIS") == key("#vndis"));
+        assert!(Channel::log_file("#vn_dis") == "vn_dis.log");
+        assert!(Channel::log_file("&local") == "%26local.log");
+        assert!(Channel::log_file("##vndis") == "%23vndis.log");
+        assert!(Channel::log_file("#../etc") == "%2E%2E%2Fetc.log");
+        assert!(Channel::log_file("#c-lang") == "c%2Dlang.log");
+        assert!(Channel::log_file("#100%") == "100%25.log");
+
+        //Distinct channels get distinct files.
+        assert!(Channel::log_file("#foo.bar") != Channel::log_file("#foo_bar"));
+        assert!(Channel::log_file("#foo.bar") == "foo%2Ebar.log");
+        assert!(Channel::log_file("#vndis") != Channel::log_file("&vndis"));
+    }
+
+    #[test]
+    fn test_rejoin() {
+        let mut chan = Channel::new("#vndis");
+        chan.join();
+        assert!(chan.joined);
+
+        chan.kicked("Douman", "bad bot");
+        assert!(!chan.joined);
+        assert!(format!("{}", chan).starts_with("#vndis(joined=false, users=0, kicked by Douman: bad bot)"));
+
+        assert!(chan.rejoin_failed(3));
+        assert!(chan.rejoin_failed(3));
+        assert!(!chan.rejoin_failed(3));
+
+        chan.join();
+        assert!(chan.rejoin_attempts == 0);
+
+        chan.kicked("Douman", "bad bot");
+        chan.rejoin_at = Some(100);
+        assert!(!chan.should_rejoin(99));
+        assert!(chan.should_rejoin(100));
+        assert!(!chan.should_rejoin(101));
+
+        //Channel which is joined meanwhile is skipped.
+        chan.rejoin_at = Some(100);
+        chan.joined = true;
+        assert!(!chan.should_rejoin(101));
+        chan.rejoin_at = Some(100);
+        chan.join();
+        assert!(chan.rejoin_at.is_none());
+
+        std::fs::remove_file("vndis.log").unwrap_or(());
+    }
+}
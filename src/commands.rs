@@ -0,0 +1,374 @@
+//! Bot commands
+
+use std;
+
+use acl::Role;
+use channel;
+use {KuuBot, BotResponse};
+
+///Describes how command can be invoked.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Trigger {
+    ///Only when bot is addressed i.e. `Kuu: cmd`
+    Direct,
+    ///Also via `!cmd`
+    Any
+}
+
+///Bot's command.
+pub trait Command {
+    ///Main name of command.
+    fn name(&self) -> &'static str;
+
+    ///Alternative names of command.
+    fn aliases(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    fn trigger(&self) -> Trigger {
+        Trigger::Direct
+    }
+
+    ///Least role that is allowed to invoke command.
+    fn access(&self) -> Role {
+        Role::User
+    }
+
+    ///Least role that is allowed to invoke command with arguments.
+    ///
+    ///Command can restrict some of its sub-commands further than `access`.
+    fn access_with(&self, _args: &[&str]) -> Role {
+        self.access()
+    }
+
+    ///Name with arguments to be shown in help.
+    fn usage(&self) -> &'static str {
+        self.name()
+    }
+
+    ///Short description of command.
+    fn help(&self) -> &'static str;
+
+    ///Whether command should be omitted from help.
+    fn hidden(&self) -> bool {
+        false
+    }
+
+    ///Response to someone who is not allowed to invoke command.
+    fn denied(&self) -> BotResponse {
+        BotResponse::Channel("It is for my master only".to_string())
+    }
+
+    ///Executes command with arguments that follow its name.
+    fn exec(&self, bot: &KuuBot, nickname: &String, args: &[&str], chan: &mut channel::Channel) -> BotResponse;
+}
+
+///Collection of bot's commands.
+pub struct Registry {
+    inner: Vec<Box<Command>>
+}
+
+impl Registry {
+    #[inline(always)]
+    ///Creates empty registry.
+    pub fn new() -> Registry {
+        Registry {
+            inner: Vec::new()
+        }
+    }
+
+    ///Creates registry with all bot's commands.
+    pub fn with_defaults() -> Registry {
+        let mut result = Registry::new();
+        result.add(Box::new(Ping));
+        result.add(Box::new(Grep));
+        result.add(Box::new(Google));
+        result.add(Box::new(Log));
+        result.add(Box::new(Who));
+        result.add(Box::new(Seen));
+        result.add(Box::new(Tell));
+        result.add(Box::new(Memo));
+        result.add(Box::new(Remind));
+        result.add(Box::new(Greet));
+        result.add(Box::new(About));
+        result.add(Box::new(Help));
+        result.add(Box::new(Sleep));
+        result.add(Box::new(Die));
+        result.add(Box::new(Rape));
+        result.add(Box::new(Huiping));
+        result
+    }
+
+    #[inline(always)]
+    pub fn add(&mut self, cmd: Box<Command>) {
+        self.inner.push(cmd);
+    }
+
+    ///Looks up command by its name or alias.
+    pub fn find(&self, name: &str) -> Option<&Command> {
+        self.inner.iter()
+                  .find(|cmd| cmd.name() == name || cmd.aliases().iter().any(|alias| *alias == name))
+                  .map(|cmd| &**cmd)
+    }
+
+    #[inline(always)]
+    pub fn iter(&self) -> std::slice::Iter<Box<Command>> {
+        self.inner.iter()
+    }
+
+    ///Returns help message with all visible commands.
+    pub fn usage(&self) -> String {
+        self.iter().filter(|cmd| !cmd.hidden()).fold("Available commands:\n".to_string(), |acc, cmd| {
+            let mut line = format!("{:<12}- {}.", cmd.usage(), cmd.help());
+
+            if cmd.trigger() == Trigger::Any {
+                line.push_str(" Available via !");
+            }
+            match cmd.access() {
+                Role::Owner   => line.push_str(" Master only."),
+                Role::Trusted => line.push_str(" Trusted only."),
+                _             => (),
+            }
+
+            acc + &line + "\n"
+        })
+    }
+}
+
+struct Ping;
+impl Command for Ping {
+    fn name(&self) -> &'static str { "ping" }
+    fn aliases(&self) -> &'static [&'static str] { &["пинг"] }
+    fn trigger(&self) -> Trigger { Trigger::Any }
+    fn help(&self) -> &'static str { "to get pong in response" }
+
+    fn exec(&self, _: &KuuBot, _: &String, _: &[&str], _: &mut channel::Channel) -> BotResponse {
+        BotResponse::Channel("pong".to_string())
+    }
+}
+
+struct Grep;
+impl Command for Grep {
+    fn name(&self) -> &'static str { "grep" }
+    fn aliases(&self) -> &'static [&'static str] { &["find"] }
+    fn usage(&self) -> &'static str { "grep vn <title>" }
+    fn help(&self) -> &'static str { "to get search link on vndb" }
+
+    fn exec(&self, _: &KuuBot, _: &String, args: &[&str], _: &mut channel::Channel) -> BotResponse {
+        KuuBot::command_grep(args)
+    }
+}
+
+struct Google;
+impl Command for Google {
+    fn name(&self) -> &'static str { "google" }
+    fn usage(&self) -> &'static str { "google <text>" }
+    fn help(&self) -> &'static str { "to get search link on google" }
+
+    fn exec(&self, _: &KuuBot, _: &String, args: &[&str], _: &mut channel::Channel) -> BotResponse {
+        KuuBot::command_google(args)
+    }
+}
+
+struct Log;
+impl Command for Log {
+    fn name(&self) -> &'static str { "log" }
+    fn trigger(&self) -> Trigger { Trigger::Any }
+    fn usage(&self) -> &'static str { "log <cmd>" }
+    fn help(&self) -> &'static str { "access to log facilities. See log help for more information" }
+
+    ///Dump uploads whole log, so it is for trusted only.
+    fn access_with(&self, args: &[&str]) -> Role {
+        match args.first() {
+            Some(sub) if sub.to_lowercase() == "dump" => Role::Trusted,
+            _                                         => self.access(),
+        }
+    }
+
+    fn denied(&self) -> BotResponse {
+        BotResponse::Channel("Log dump is for trusted only".to_string())
+    }
+
+    fn exec(&self, bot: &KuuBot, nickname: &String, args: &[&str], chan: &mut channel::Channel) -> BotResponse {
+        bot.command_log(nickname, args, chan)
+    }
+}
+
+struct Who;
+impl Command for Who {
+    fn name(&self) -> &'static str { "who" }
+    fn aliases(&self) -> &'static [&'static str] { &["users"] }
+    fn trigger(&self) -> Trigger { Trigger::Any }
+    fn usage(&self) -> &'static str { "who [nick]" }
+    fn help(&self) -> &'static str { "to get list of users on channel or details about one" }
+
+    fn exec(&self, _: &KuuBot, _: &String, args: &[&str], chan: &mut channel::Channel) -> BotResponse {
+        KuuBot::command_who(args, chan)
+    }
+}
+
+struct Seen;
+impl Command for Seen {
+    fn name(&self) -> &'static str { "seen" }
+    fn trigger(&self) -> Trigger { Trigger::Any }
+    fn usage(&self) -> &'static str { "seen <nick>" }
+    fn help(&self) -> &'static str { "to get when user was seen last time" }
+
+    fn exec(&self, bot: &KuuBot, nickname: &String, args: &[&str], chan: &mut channel::Channel) -> BotResponse {
+        bot.command_seen(nickname, args, chan)
+    }
+}
+
+struct Tell;
+impl Command for Tell {
+    fn name(&self) -> &'static str { "tell" }
+    fn trigger(&self) -> Trigger { Trigger::Any }
+    fn usage(&self) -> &'static str { "tell <nick> <text>" }
+    fn help(&self) -> &'static str { "to leave message which I'll pass when user is around" }
+
+    fn exec(&self, bot: &KuuBot, nickname: &String, args: &[&str], chan: &mut channel::Channel) -> BotResponse {
+        bot.command_tell(nickname, args, chan)
+    }
+}
+
+struct Memo;
+impl Command for Memo {
+    fn name(&self) -> &'static str { "memo" }
+    fn trigger(&self) -> Trigger { Trigger::Any }
+    fn usage(&self) -> &'static str { "memo [cancel <id>]" }
+    fn help(&self) -> &'static str { "to list or cancel your pending messages" }
+
+    fn exec(&self, bot: &KuuBot, nickname: &String, args: &[&str], _: &mut channel::Channel) -> BotResponse {
+        bot.command_memo(nickname, args)
+    }
+}
+
+struct Remind;
+impl Command for Remind {
+    fn name(&self) -> &'static str { "remind" }
+    fn trigger(&self) -> Trigger { Trigger::Any }
+    fn usage(&self) -> &'static str { "remind <when> <text>" }
+    fn help(&self) -> &'static str { "to remind you e.g. remind me in 2h30m to sleep. See remind help" }
+
+    fn exec(&self, bot: &KuuBot, nickname: &String, args: &[&str], chan: &mut channel::Channel) -> BotResponse {
+        bot.command_remind(nickname, args, chan)
+    }
+}
+
+struct Greet;
+impl Command for Greet {
+    fn name(&self) -> &'static str { "greet" }
+    fn usage(&self) -> &'static str { "greet [<text>|off]" }
+    fn help(&self) -> &'static str { "to set, show or turn off my greeting when you join channel" }
+
+    fn exec(&self, bot: &KuuBot, nickname: &String, args: &[&str], chan: &mut channel::Channel) -> BotResponse {
+        bot.command_greet(nickname, args, chan)
+    }
+}
+
+struct About;
+impl Command for About {
+    fn name(&self) -> &'static str { "about" }
+    fn aliases(&self) -> &'static [&'static str] { &["status"] }
+    fn access(&self) -> Role { Role::Owner }
+    fn help(&self) -> &'static str { "to get my status" }
+
+    fn exec(&self, bot: &KuuBot, _: &String, _: &[&str], chan: &mut channel::Channel) -> BotResponse {
+        bot.command_about(chan)
+    }
+}
+
+struct Help;
+impl Command for Help {
+    fn name(&self) -> &'static str { "help" }
+    fn help(&self) -> &'static str { "to get this message" }
+
+    fn exec(&self, bot: &KuuBot, _: &String, _: &[&str], _: &mut channel::Channel) -> BotResponse {
+        bot.command_help()
+    }
+}
+
+struct Sleep;
+impl Command for Sleep {
+    fn name(&self) -> &'static str { "sleep" }
+    fn aliases(&self) -> &'static [&'static str] { &["shutdown", "bye"] }
+    fn access(&self) -> Role { Role::Owner }
+    fn help(&self) -> &'static str { "to gently shut me down" }
+
+    fn exec(&self, _: &KuuBot, _: &String, _: &[&str], _: &mut channel::Channel) -> BotResponse {
+        BotResponse::DyingMsg
+    }
+}
+
+struct Die;
+impl Command for Die {
+    fn name(&self) -> &'static str { "die" }
+    fn access(&self) -> Role { Role::Owner }
+    fn help(&self) -> &'static str { "to shut me down" }
+
+    fn denied(&self) -> BotResponse {
+        BotResponse::Channel("死になさいゴミムシ".to_string())
+    }
+
+    fn exec(&self, _: &KuuBot, _: &String, _: &[&str], _: &mut channel::Channel) -> BotResponse {
+        BotResponse::DyingMsg
+    }
+}
+
+struct Rape;
+impl Command for Rape {
+    fn name(&self) -> &'static str { "rape" }
+    fn help(&self) -> &'static str { "..." }
+    fn hidden(&self) -> bool { true }
+
+    fn exec(&self, _: &KuuBot, _: &String, args: &[&str], _: &mut channel::Channel) -> BotResponse {
+        KuuBot::command_rape(args)
+    }
+}
+
+struct Huiping;
+impl Command for Huiping {
+    fn name(&self) -> &'static str { "huiping" }
+    fn aliases(&self) -> &'static [&'static str] { &["хуйпинг"] }
+    fn trigger(&self) -> Trigger { Trigger::Any }
+    fn help(&self) -> &'static str { "..." }
+    fn hidden(&self) -> bool { true }
+
+    fn exec(&self, _: &KuuBot, _: &String, _: &[&str], _: &mut channel::Channel) -> BotResponse {
+        BotResponse::Channel("死になさいゴミムシ".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_find() {
+        let registry = Registry::with_defaults();
+
+        assert!(registry.find("ping").map(|cmd| cmd.name()) == Some("ping"));
+        assert!(registry.find("пинг").map(|cmd| cmd.name()) == Some("ping"));
+        assert!(registry.find("bye").map(|cmd| cmd.name()) == Some("sleep"));
+        assert!(registry.find("die").map(|cmd| cmd.access()) == Some(Role::Owner));
+        assert!(registry.find("log").map(|cmd| cmd.trigger()) == Some(Trigger::Any));
+        assert!(registry.find("log").map(|cmd| cmd.access_with(&["last"])) == Some(Role::User));
+        assert!(registry.find("log").map(|cmd| cmd.access_with(&["DUMP"])) == Some(Role::Trusted));
+        assert!(registry.find("die").map(|cmd| cmd.access_with(&[])) == Some(Role::Owner));
+        assert!(registry.find("users").map(|cmd| cmd.name()) == Some("who"));
+        assert!(registry.find("nya").is_none());
+    }
+
+    #[test]
+    fn test_registry_usage() {
+        let registry = Registry::with_defaults();
+        let usage = registry.usage();
+
+        assert!(usage.starts_with("Available commands:\n"));
+        assert!(usage.lines().count() == 1 + registry.iter().filter(|cmd| !cmd.hidden()).count());
+        assert!(usage.lines().any(|line| line.starts_with("about")));
+        assert!(usage.lines().any(|line| line.starts_with("die")));
+        assert!(usage.lines().any(|line| line.starts_with("sleep")));
+        assert!(!usage.lines().any(|line| line.starts_with("rape")));
+    }
+}
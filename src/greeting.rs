@@ -0,0 +1,231 @@
+//! Greetings on JOIN
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use acl;
+use utils;
+
+///Number of joins within `STORM_WINDOW` seconds which is considered as rejoin storm.
+const STORM_JOINS: usize = 5;
+const STORM_WINDOW: i64 = 10;
+///Time in seconds during which user who quit due to netsplit is not greeted on rejoin.
+const SPLIT_TIMEOUT: i64 = 1800;
+
+///Greeting for users who match hostmask.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Greeting {
+    ///Hostmask pattern `nick!user@host` with wildcards.
+    pub mask: String,
+    ///Channel or `*` for any channel.
+    pub channel: String,
+    pub text: String
+}
+
+impl Greeting {
+    ///Parses line of state file.
+    fn parse(line: &str) -> Option<Greeting> {
+        let parts: Vec<&str> = line.splitn(3, '\t').collect();
+        if parts.len() < 3 {
+            return None;
+        }
+
+        Some(Greeting {
+            mask: parts[0].to_lowercase(),
+            channel: parts[1].to_lowercase(),
+            text: parts[2].to_string()
+        })
+    }
+}
+
+impl fmt::Display for Greeting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}\t{}\t{}", self.mask, self.channel, utils::state_field(&self.text))
+    }
+}
+
+///Checks whether QUIT reason is netsplit i.e. `irc.server.net hub.server.net`
+pub fn is_netsplit(reason: &str) -> bool {
+    let servers: Vec<&str> = reason.split(' ').collect();
+
+    servers.len() == 2 && servers.iter().all(|server| {
+        server.contains('.') && !server.starts_with('.') && !server.ends_with('.') &&
+        server.chars().all(|ch| ch.is_alphanumeric() || ch == '.' || ch == '-' || ch == '*')
+    })
+}
+
+///Persistent greetings with flood protection.
+pub struct Greetings {
+    path: String,
+    inner: Vec<Greeting>,
+    ///Time in seconds during which user is not greeted again.
+    cooldown: i64,
+    ///Time of last greeting per `nick channel`.
+    greeted: HashMap<String, i64>,
+    ///Time of recent joins.
+    joins: VecDeque<i64>,
+    ///Time when users quit due to netsplit.
+    split: HashMap<String, i64>
+}
+
+impl Greetings {
+    ///Loads greetings from file, if it exists.
+    pub fn open(path: &str, cooldown: i64) -> Greetings {
+        Greetings {
+            path: path.to_string(),
+            inner: utils::load_state(path, Greeting::parse),
+            cooldown: cooldown,
+            greeted: HashMap::new(),
+            joins: VecDeque::new(),
+            split: HashMap::new()
+        }
+    }
+
+    #[inline(always)]
+    pub fn set_cooldown(&mut self, cooldown: i64) {
+        self.cooldown = cooldown;
+    }
+
+    ///Returns greeting for user with prefix `nick!user@host` on channel.
+    ///
+    ///Greeting for the channel takes priority over greeting for any channel.
+    pub fn find(&self, prefix: &str, channel: &str) -> Option<&Greeting> {
+        let prefix = prefix.to_lowercase();
+        let channel = channel.to_lowercase();
+        let matches = |greeting: &&Greeting| acl::mask_match(&greeting.mask, &prefix);
+
+        self.inner.iter().filter(&matches).find(|greeting| greeting.channel == channel)
+                  .or_else(|| self.inner.iter().filter(&matches).find(|greeting| greeting.channel == "*"))
+    }
+
+    ///Sets greeting replacing one with the same mask and channel.
+    pub fn set(&mut self, mask: &str, channel: &str, text: &str) {
+        let greeting = Greeting {
+            mask: mask.to_lowercase(),
+            channel: channel.to_lowercase(),
+            text: text.to_string()
+        };
+
+        match self.inner.iter().position(|old| old.mask == greeting.mask && old.channel == greeting.channel) {
+            Some(idx) => self.inner[idx] = greeting,
+            None      => self.inner.push(greeting),
+        }
+        self.save();
+    }
+
+    ///Removes greeting.
+    ///
+    ///Returns false if there is no such greeting.
+    pub fn remove(&mut self, mask: &str, channel: &str) -> bool {
+        let (mask, channel) = (mask.to_lowercase(), channel.to_lowercase());
+        let len = self.inner.len();
+        self.inner.retain(|greeting| greeting.mask != mask || greeting.channel != channel);
+
+        if self.inner.len() == len {
+            return false;
+        }
+
+        self.save();
+        true
+    }
+
+    #[inline]
+    ///Records that user quit due to netsplit.
+    pub fn split(&mut self, nick: &str, now: i64) {
+        self.split.insert(nick.to_lowercase(), now);
+    }
+
+    ///Records JOIN and returns whether user can be greeted.
+    ///
+    ///Users are not greeted during rejoin storms, after netsplit and within cooldown.
+    pub fn should_greet(&mut self, nick: &str, channel: &str, now: i64) -> bool {
+        self.joins.push_back(now);
+        while self.joins.front().map_or(false, |time| now - *time >= STORM_WINDOW) {
+            self.joins.pop_front();
+        }
+
+        let nick = nick.to_lowercase();
+        //User may rejoin several channels after netsplit, so mark expires by time only.
+        self.split.retain(|_, time| now - *time < SPLIT_TIMEOUT);
+        let is_split = self.split.contains_key(&nick);
+        if is_split || self.joins.len() >= STORM_JOINS {
+            return false;
+        }
+
+        let key = format!("{} {}", nick, channel.to_lowercase());
+        if self.greeted.get(&key).map_or(false, |time| now - *time < self.cooldown) {
+            return false;
+        }
+
+        let cooldown = self.cooldown;
+        self.greeted.retain(|_, time| now - *time < cooldown);
+        self.greeted.insert(key, now);
+        true
+    }
+
+    ///Writes greetings to file, but not flood protection state which is reset on restart.
+    pub fn save(&self) {
+        utils::save_state(&self.path, self.inner.iter());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std;
+
+    #[test]
+    fn test_is_netsplit() {
+        assert!(is_netsplit("irc.rizon.net hub.rizon.net"));
+        assert!(is_netsplit("*.net *.split"));
+
+        assert!(!is_netsplit("Quit: bye"));
+        assert!(!is_netsplit("Ping timeout: 240 seconds"));
+        assert!(!is_netsplit("see you.later guys."));
+    }
+
+    #[test]
+    fn test_greetings() {
+        let path = "test_greetings.txt";
+        {
+            let mut greetings = Greetings::open(path, 3600);
+            greetings.set("*!douman@*", "*", "Welcome, dear master!");
+            greetings.set("*!douman@*", "#vndis", "Okaeri, master!");
+            greetings.set("*!kuu@*", "#vndis", "nya");
+
+            assert!(greetings.find("Douman!douman@irc.net", "#VNDIS").unwrap().text == "Okaeri, master!");
+            assert!(greetings.find("Douman!douman@irc.net", "#other").unwrap().text == "Welcome, dear master!");
+            assert!(greetings.find("Kuu!kuu@irc.net", "#other").is_none());
+
+            assert!(greetings.remove("*!Kuu@*", "#vndis"));
+            assert!(!greetings.remove("*!Kuu@*", "#vndis"));
+        }
+
+        let mut greetings = Greetings::open(path, 3600);
+        assert!(greetings.find("Douman!douman@irc.net", "#vndis").unwrap().text == "Okaeri, master!");
+        assert!(greetings.find("Kuu!kuu@irc.net", "#vndis").is_none());
+
+        //Cooldown
+        assert!(greetings.should_greet("Douman", "#vndis", 1000));
+        assert!(!greetings.should_greet("douman", "#vndis", 2000));
+        assert!(greetings.should_greet("Douman", "#other", 2000));
+        assert!(greetings.should_greet("Douman", "#vndis", 4600));
+
+        //Netsplit
+        greetings.split("Kuu", 5000);
+        assert!(!greetings.should_greet("Kuu", "#vndis", 5100));
+        assert!(!greetings.should_greet("Kuu", "#other", 5200));
+
+        //Rejoin storm
+        for idx in 0..4 {
+            greetings.should_greet(&format!("User{}", idx), "#vndis", 6000);
+        }
+        assert!(!greetings.should_greet("Someone", "#vndis", 6001));
+        assert!(greetings.should_greet("Someone", "#vndis", 6100));
+
+        //Netsplit mark expires.
+        assert!(greetings.should_greet("Kuu", "#other", 6800));
+
+        std::fs::remove_file(path).unwrap_or(());
+    }
+}
@@ -0,0 +1,230 @@
+//! NickServ identity verification
+
+extern crate time;
+
+use irc::client::prelude::Message;
+
+use std::collections::HashMap;
+
+///Time in seconds after which unanswered verification is considered as failed.
+const PENDING_TIMEOUT: i64 = 60;
+///Max number of messages which wait for verification of one user.
+const PENDING_LIMIT: usize = 10;
+
+///Way to ask for user's identity.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Method {
+    ///WHOIS with RPL_WHOISACCOUNT(330) or RPL_WHOISREGNICK(307)
+    Whois,
+    ///`STATUS nick` to NickServ (Anope)
+    Status,
+    ///`ACC nick` to NickServ (Atheme)
+    Acc
+}
+
+impl Method {
+    pub fn parse(name: &str) -> Option<Method> {
+        match &name.to_lowercase()[..] {
+            "whois"  => Some(Method::Whois),
+            "status" => Some(Method::Status),
+            "acc"    => Some(Method::Acc),
+            _        => None,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Status {
+    ///Verification is requested at specified time(in seconds).
+    Pending(i64),
+    ///User is logged in as account.
+    Account(String),
+    NotIdentified
+}
+
+///Cache of users' identities.
+pub struct Identities {
+    cache: HashMap<String, Status>,
+    ///Messages which wait for sender's verification.
+    pending: HashMap<String, Vec<Message>>
+}
+
+impl Identities {
+    #[inline(always)]
+    pub fn new() -> Identities {
+        Identities {
+            cache: HashMap::new(),
+            pending: HashMap::new()
+        }
+    }
+
+    #[inline(always)]
+    fn key(nickname: &str) -> String {
+        nickname.to_lowercase()
+    }
+
+    #[inline]
+    ///Returns whether identity of user is known i.e. verification is completed.
+    ///
+    ///Both positive and negative results are kept until NICK or QUIT, so that user cannot make bot flood NickServ.
+    ///Verification without answer counts as negative one.
+    pub fn is_known(&self, nickname: &str) -> bool {
+        match self.cache.get(&Identities::key(nickname)) {
+            Some(&Status::Account(_)) | Some(&Status::NotIdentified) => true,
+            Some(&Status::Pending(since))                            => time::get_time().sec - since > PENDING_TIMEOUT,
+            None                                                     => false,
+        }
+    }
+
+    #[inline]
+    ///Returns account of verified user.
+    pub fn account(&self, nickname: &str) -> Option<&str> {
+        match self.cache.get(&Identities::key(nickname)) {
+            Some(&Status::Account(ref account)) => Some(&account[..]),
+            _ => None,
+        }
+    }
+
+    ///Postpones message until sender is verified.
+    ///
+    ///Returns true if verification should be requested i.e. only once per user.
+    pub fn wait(&mut self, nickname: &str, message: Message) -> bool {
+        let key = Identities::key(nickname);
+
+        {
+            let pending = self.pending.entry(key.clone()).or_insert(Vec::new());
+            if pending.len() < PENDING_LIMIT {
+                pending.push(message);
+            }
+        }
+
+        if self.cache.contains_key(&key) {
+            return false;
+        }
+
+        self.cache.insert(key, Status::Pending(time::get_time().sec));
+        true
+    }
+
+    ///Sets result of verification.
+    ///
+    ///Returns messages which were waiting for it.
+    pub fn resolve(&mut self, nickname: &str, account: Option<String>) -> Vec<Message> {
+        let key = Identities::key(nickname);
+
+        self.cache.insert(key.clone(), account.map_or(Status::NotIdentified, |account| Status::Account(account)));
+        self.pending.remove(&key).unwrap_or(Vec::new())
+    }
+
+    #[inline]
+    ///Finishes verification which got no account.
+    ///
+    ///Returns messages which were waiting for it.
+    pub fn finish(&mut self, nickname: &str) -> Vec<Message> {
+        match self.cache.get(&Identities::key(nickname)) {
+            Some(&Status::Pending(_)) => (),
+            _                         => return Vec::new(),
+        }
+
+        self.resolve(nickname, None)
+    }
+
+    #[inline]
+    ///Forgets user's identity e.g. on NICK or QUIT.
+    pub fn forget(&mut self, nickname: &str) {
+        let key = Identities::key(nickname);
+        self.cache.remove(&key);
+        self.pending.remove(&key);
+    }
+
+    #[inline(always)]
+    pub fn clear(&mut self) {
+        self.cache.clear();
+        self.pending.clear();
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+}
+
+///Parses NickServ reply on `STATUS` or `ACC`.
+///
+///Returns nickname and whether it is identified for its account.
+pub fn parse_nickserv(text: &str) -> Option<(String, bool)> {
+    let parts: Vec<&str> = text.split_whitespace().collect();
+
+    if parts.len() < 3 {
+        None
+    }
+    //STATUS <nick> <level>
+    else if parts[0] == "STATUS" {
+        Some((parts[1].to_string(), parts[2] == "3"))
+    }
+    //<nick> ACC <level>
+    else if parts[1] == "ACC" {
+        Some((parts[0].to_string(), parts[2] == "3"))
+    }
+    else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate time;
+    use super::*;
+    use super::{PENDING_LIMIT, PENDING_TIMEOUT};
+    use irc::client::prelude::Message;
+
+    #[test]
+    fn test_parse_nickserv() {
+        assert!(parse_nickserv("STATUS Douman 3") == Some(("Douman".to_string(), true)));
+        assert!(parse_nickserv("STATUS Douman 1") == Some(("Douman".to_string(), false)));
+        assert!(parse_nickserv("Douman ACC 3") == Some(("Douman".to_string(), true)));
+        assert!(parse_nickserv("Douman ACC 0 (not registered)") == Some(("Douman".to_string(), false)));
+        assert!(parse_nickserv("Password accepted") == None);
+    }
+
+    #[test]
+    fn test_identities() {
+        let message = Message::new(Some("Douman!douman@irc.net"), "PRIVMSG", Some(vec!["#vndis"]), Some("Kuu: die"));
+        let mut identities = Identities::new();
+
+        assert!(!identities.is_known("Douman"));
+        assert!(identities.wait("Douman", message.clone()));
+        assert!(!identities.wait("douman", message.clone()));
+        assert!(!identities.is_known("Douman"));
+
+        let pending = identities.resolve("Douman", Some("douman".to_string()));
+        assert!(pending.len() == 2);
+        assert!(identities.is_known("Douman"));
+        assert!(identities.account("DOUMAN") == Some("douman"));
+        assert!(identities.finish("Douman").is_empty());
+        assert!(identities.account("Douman") == Some("douman"));
+
+        identities.forget("Douman");
+        assert!(!identities.is_known("Douman"));
+        assert!(identities.account("Douman").is_none());
+
+        assert!(identities.wait("Douman", message.clone()));
+        assert!(identities.finish("Douman").len() == 1);
+        assert!(identities.is_known("Douman"));
+        assert!(identities.account("Douman").is_none());
+        //Negative result is kept.
+        assert!(!identities.wait("Douman", message.clone()));
+
+        //Unanswered verification is not requested again and is considered as failed after timeout.
+        identities.forget("Douman");
+        assert!(identities.wait("Douman", message.clone()));
+        for _ in 0..PENDING_LIMIT * 2 {
+            assert!(!identities.wait("Douman", message.clone()));
+        }
+        assert!(identities.pending.get("douman").unwrap().len() == PENDING_LIMIT);
+        assert!(!identities.is_known("Douman"));
+        identities.cache.insert("douman".to_string(), Status::Pending(time::get_time().sec - PENDING_TIMEOUT - 1));
+        assert!(identities.is_known("Douman"));
+        assert!(identities.account("Douman").is_none());
+    }
+}
@@ -9,41 +9,49 @@ use std::fmt;
 use std::io::{Write, Read, Seek, BufRead};
 use std;
 
+use rustc_serialize::json;
 use utils;
 
-const TIME_FORMAT: &'static str = "%x %X";
+///Format of human readable time.
+pub const TIME_FORMAT: &'static str = "%x %X";
+///RFC 3339 time in UTC which is used on disk.
+const UTC_FORMAT: &'static str = "%Y-%m-%dT%H:%M:%SZ";
+///Date in names of rotated log files.
+const DAY_FORMAT: &'static str = "%Y-%m-%d";
+///Number of bytes between points of log index.
+const INDEX_STEP: u64 = 65536;
+///Version of on-disk format.
+///
+///* 0 - `[%x %X] <nick> message` lines;
+///* 1 - JSON Lines.
+pub const FORMAT_VERSION: u32 = 1;
+
+///Returns local time at moment `sec` given UTC offset at that moment.
+pub fn local_tm(sec: i64, utcoff: i32) -> time::Tm {
+    let mut tm = time::at_utc(time::Timespec::new(sec + utcoff as i64, 0));
+    tm.tm_utcoff = utcoff;
+    tm
+}
+
+///Returns UTC offset of local time at moment `sec`.
+#[inline(always)]
+pub fn local_utcoff(sec: i64) -> i32 {
+    time::at(time::Timespec::new(sec, 0)).tm_utcoff
+}
 
 pub enum FilterLog {
     None,
-    Last(time::Tm)
+    ///Entries after moment in seconds since epoch.
+    Last(i64)
 }
 
 impl FilterLog {
     ///Checks if given element is within allowed time range
     #[inline]
-    pub fn check(&self, time: &time::Tm) -> bool {
+    pub fn check(&self, time: i64) -> bool {
         match *self {
             FilterLog::None => true,
-            FilterLog::Last(from) => {
-                //@TODO: this is a hacky date comparing via seconds conversion.
-                //       But for now live with it.
-                //       The issue: you get bad Tm from file with strptime()
-                //       NOTE: That strptime() sets year as 15, while Tm would have 115.
-                let from_seconds: i64 = if from.tm_year > 100 { from.tm_year - 100 } else { from.tm_year } as i64 * 31536000 +
-                                        (from.tm_mon * 2592000) as i64 +
-                                        (from.tm_mday * 86400) as i64 +
-                                        (from.tm_hour * 3600) as i64 +
-                                        (from.tm_min * 60) as i64 +
-                                        from.tm_sec as i64;
-                let time_seconds: i64 = if time.tm_year > 100 { time.tm_year - 100 } else { time.tm_year } as i64 * 31536000 +
-                                        (time.tm_mon * 2592000) as i64 +
-                                        (time.tm_mday * 86400) as i64 +
-                                        (time.tm_hour * 3600) as i64 +
-                                        (time.tm_min * 60) as i64 +
-                                        time.tm_sec as i64;
-
-                from_seconds < time_seconds
-            },
+            FilterLog::Last(from) => from < time,
         }
     }
 }
@@ -52,102 +60,734 @@ impl fmt::Display for FilterLog {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match *self {
             FilterLog::None => write!(f, "None"),
-            FilterLog::Last(from) => write!(f, "Last({})", from.strftime(TIME_FORMAT).unwrap()),
+            FilterLog::Last(from) => write!(f, "Last({})", local_tm(from, local_utcoff(from)).strftime(TIME_FORMAT).unwrap()),
+        }
+    }
+}
+
+///Kind of log entry.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Kind {
+    Message,
+    Action,
+    Notice,
+    Join,
+    Part,
+    Quit,
+    Kick,
+    Nick,
+    Topic,
+    Mode
+}
+
+pub const KINDS: [Kind; 10] = [Kind::Message, Kind::Action, Kind::Notice, Kind::Join, Kind::Part,
+                               Kind::Quit, Kind::Kick, Kind::Nick, Kind::Topic, Kind::Mode];
+
+impl Kind {
+    pub fn parse(name: &str) -> Option<Kind> {
+        KINDS.iter().find(|kind| kind.name() == name).cloned()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Kind::Message => "message",
+            Kind::Action  => "action",
+            Kind::Notice  => "notice",
+            Kind::Join    => "join",
+            Kind::Part    => "part",
+            Kind::Quit    => "quit",
+            Kind::Kick    => "kick",
+            Kind::Nick    => "nick",
+            Kind::Topic   => "topic",
+            Kind::Mode    => "mode",
+        }
+    }
+
+    #[inline(always)]
+    fn bit(&self) -> u16 {
+        1 << KINDS.iter().position(|kind| kind == self).unwrap()
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.write_str(self.name())
+    }
+}
+
+///Set of entry kinds to include into log dump.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct KindFilter {
+    mask: u16
+}
+
+impl KindFilter {
+    #[inline(always)]
+    pub fn all() -> KindFilter {
+        KindFilter {
+            mask: (1 << KINDS.len()) - 1
+        }
+    }
+
+    ///Parses list of kinds i.e. `+join +part` or `-mode -nick`.
+    ///
+    ///Kinds prefixed by `+` or without prefix are included, others are excluded.
+    ///If anything is included then nothing else is, otherwise all kinds except excluded ones.
+    pub fn parse(args: &[&str]) -> Result<KindFilter, String> {
+        let mut include = 0;
+        let mut exclude = 0;
+
+        for arg in args.iter() {
+            let arg = arg.to_lowercase();
+            let (is_exclude, name) = if arg.starts_with('-') {
+                (true, &arg[1..])
+            }
+            else {
+                (false, arg.trim_left_matches('+'))
+            };
+
+            match Kind::parse(name) {
+                Some(kind) => if is_exclude { exclude |= kind.bit() } else { include |= kind.bit() },
+                None       => return Err(format!("unknown kind '{}'. Allowed: {}", name,
+                                                 KINDS.iter().map(|kind| kind.name()).collect::<Vec<_>>().join(", "))),
+            }
+        }
+
+        let mask = if include == 0 { KindFilter::all().mask } else { include };
+        Ok(KindFilter {
+            mask: mask & !exclude
+        })
+    }
+
+    #[inline(always)]
+    pub fn check(&self, kind: Kind) -> bool {
+        self.mask & kind.bit() != 0
+    }
+}
+
+impl fmt::Display for KindFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        if *self == KindFilter::all() {
+            return write!(f, "All");
+        }
+
+        let kinds = KINDS.iter().filter(|kind| self.check(**kind)).map(|kind| kind.name()).collect::<Vec<_>>();
+        write!(f, "[{}]", kinds.join(","))
+    }
+}
+
+///Filter of log entries.
+pub trait Filter {
+    fn accepts(&self, entry: &IrcEntry) -> bool;
+
+    ///Returns moment before which no entries are accepted, if any.
+    fn since(&self) -> Option<i64> {
+        None
+    }
+}
+
+impl Filter for FilterLog {
+    #[inline(always)]
+    fn accepts(&self, entry: &IrcEntry) -> bool {
+        self.check(entry.time)
+    }
+
+    #[inline(always)]
+    fn since(&self) -> Option<i64> {
+        match *self {
+            FilterLog::None => None,
+            FilterLog::Last(from) => Some(from),
+        }
+    }
+}
+
+impl Filter for KindFilter {
+    #[inline(always)]
+    fn accepts(&self, entry: &IrcEntry) -> bool {
+        self.check(entry.kind)
+    }
+}
+
+impl<A: Filter, B: Filter> Filter for (A, B) {
+    #[inline(always)]
+    fn accepts(&self, entry: &IrcEntry) -> bool {
+        self.0.accepts(entry) && self.1.accepts(entry)
+    }
+
+    #[inline(always)]
+    fn since(&self) -> Option<i64> {
+        match (self.0.since(), self.1.since()) {
+            (Some(left), Some(right)) => Some(std::cmp::max(left, right)),
+            (left, right)             => left.or(right),
+        }
+    }
+}
+
+///Returns local date of moment `sec` as `YYYY-MM-DD`.
+#[inline(always)]
+fn local_day(sec: i64, utcoff: i32) -> String {
+    local_tm(sec, utcoff).strftime(DAY_FORMAT).unwrap().to_string()
+}
+
+///Returns path to index of log file.
+#[inline(always)]
+fn index_path(path: &str) -> String {
+    format!("{}.idx", path)
+}
+
+///Loads sparse index of log file i.e. lines `<time>\t<offset>`.
+fn load_index(path: &str) -> Vec<(i64, u64)> {
+    let file = match std::fs::File::open(index_path(path)) {
+        Ok(file) => file,
+        Err(_)   => return Vec::new(),
+    };
+
+    std::io::BufReader::new(file).lines().filter_map(|line| line.ok()).filter_map(|line| {
+        let mut parts = line.split('\t');
+        match (parts.next().and_then(|time| time.parse::<i64>().ok()), parts.next().and_then(|offset| offset.parse::<u64>().ok())) {
+            (Some(time), Some(offset)) => Some((time, offset)),
+            _                          => None,
+        }
+    }).collect()
+}
+
+///Returns offset in log file from which entries after `since` start.
+fn index_offset(index: &[(i64, u64)], since: Option<i64>) -> u64 {
+    match since {
+        Some(since) => index.iter().take_while(|point| point.0 <= since).last().map_or(0, |point| point.1),
+        None        => 0,
+    }
+}
+
+///Reads up to `num` last entries of file backwards by chunks.
+fn read_last<R: Read + Seek>(file: &mut R, num: usize) -> Vec<IrcEntry> {
+    const CHUNK: u64 = 8192;
+    let mut pos = file.seek(std::io::SeekFrom::End(0)).unwrap_or(0);
+    let mut tail: Vec<u8> = Vec::new();
+    let mut result = Vec::new();
+
+    while pos > 0 && result.len() < num {
+        let len = std::cmp::min(CHUNK, pos);
+        pos -= len;
+
+        let mut chunk = vec![0; len as usize];
+        if file.seek(std::io::SeekFrom::Start(pos)).and_then(|_| file.read_exact(&mut chunk)).is_err() {
+            break;
+        }
+        chunk.extend_from_slice(&tail);
+        tail = chunk;
+
+        let text = String::from_utf8_lossy(&tail);
+        //The first line is incomplete unless file is read from the start.
+        let skip = if pos > 0 { 1 } else { 0 };
+        result = text.lines().skip(skip).collect::<Vec<_>>().into_iter().rev()
+                     .filter_map(|line| IrcEntry::parse(line))
+                     .take(num)
+                     .collect();
+    }
+
+    result.reverse();
+    result
+}
+
+///Splits search text into lowercase words.
+pub fn search_words(text: &str) -> Vec<String> {
+    text.split_whitespace().map(|word| word.to_lowercase()).collect()
+}
+
+///Kind of log store.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Backend {
+    ///Files of JSON Lines per channel.
+    File,
+    ///SQLite database with full-text index.
+    Sqlite
+}
+
+impl Backend {
+    pub fn parse(name: &str) -> Option<Backend> {
+        match name {
+            "file"   => Some(Backend::File),
+            "sqlite" => Some(Backend::Sqlite),
+            _        => None,
+        }
+    }
+}
+
+///Persistent storage of log entries.
+pub trait LogStore {
+    ///Appends entries which are ordered by time.
+    fn append(&mut self, entries: &[IrcEntry]);
+
+    ///Returns entries accepted by filter from the oldest to the newest one.
+    fn range(&mut self, filter: &Filter) -> Vec<IrcEntry>;
+
+    ///Returns number of entries accepted by filter.
+    fn count(&mut self, filter: &Filter) -> usize {
+        self.range(filter).len()
+    }
+
+    ///Returns up to `limit` newest entries which message contains all words of `text`.
+    fn search(&mut self, text: &str, limit: usize) -> Vec<IrcEntry>;
+
+    ///Returns up to `num` last entries.
+    fn last(&mut self, num: usize) -> Vec<IrcEntry>;
+
+    ///Reopens storage e.g. after it is moved by logrotate.
+    fn reopen(&mut self) {
+    }
+
+    ///Sets size in bytes after which storage is rotated. Zero means no limit.
+    fn set_max_size(&mut self, _max_size: u64) {
+    }
+}
+
+///Log file `<name>.log` of JSON Lines.
+///
+///File is rotated to `<name>-YYYY-MM-DD.log` when day changes or it grows over `max_size`.
+pub struct FileStore {
+    path: String,
+    fs_buf: std::fs::File,
+    ///Local date of the first entry in file.
+    day: Option<String>,
+    ///Size of file in bytes.
+    size: u64,
+    ///Size of file in bytes after which it is rotated. Zero means no limit.
+    max_size: u64,
+    ///Sparse index of file i.e. time of entry and its offset.
+    index: Vec<(i64, u64)>,
+    ///Minimal number of bytes between points of index.
+    index_step: u64
+}
+
+impl FileStore {
+    ///Opens file, creating it if needed.
+    pub fn open(path: &str) -> FileStore {
+        let mut result = FileStore {
+            path: path.to_string(),
+            fs_buf: FileStore::open_file(path),
+            day: None,
+            size: 0,
+            max_size: 0,
+            index: Vec::new(),
+            index_step: INDEX_STEP
+        };
+        result.load_file_state();
+        result
+    }
+
+    ///Reads size and date of the first entry of opened file.
+    fn load_file_state(&mut self) {
+        self.size = self.fs_buf.metadata().map(|data| data.len()).unwrap_or(0);
+        self.fs_buf.seek(std::io::SeekFrom::Start(0)).unwrap();
+
+        let mut first = String::new();
+        self.day = match std::io::BufReader::new(&mut self.fs_buf).read_line(&mut first) {
+            Ok(_)  => IrcEntry::parse(first.trim_right()).map(|entry| local_day(entry.time, entry.utcoff)),
+            Err(_) => None,
+        };
+
+        //Index is stale if file is replaced e.g. by logrotate.
+        let index = load_index(&self.path);
+        let size = self.size;
+        self.index = index.iter().cloned().take_while(|point| point.1 < size).collect();
+        if self.index.len() != index.len() {
+            self.save_index();
+        }
+    }
+
+    ///Rewrites index file.
+    fn save_index(&self) {
+        let result = std::fs::File::create(index_path(&self.path)).and_then(|mut file| {
+            for point in self.index.iter() {
+                try!(write!(file, "{}\t{}\n", point.0, point.1));
+            }
+            file.flush()
+        });
+
+        if let Err(err) = result {
+            println!(">>>ERROR: Unable to save index of {}: {}", &self.path, err);
+        }
+    }
+
+    ///Adds point to index, if enough is written since the last one.
+    fn update_index(&mut self, time: i64, offset: u64) {
+        if self.index.last().map_or(false, |point| offset < point.1 + self.index_step) {
+            return;
+        }
+
+        self.index.push((time, offset));
+        let result = std::fs::OpenOptions::new().append(true)
+                                                .create(true)
+                                                .open(index_path(&self.path))
+                                                .and_then(|mut file| write!(file, "{}\t{}\n", time, offset));
+
+        if let Err(err) = result {
+            println!(">>>ERROR: Unable to update index of {}: {}", &self.path, err);
         }
     }
+
+    ///Splits path to file into stem and extension i.e. `vndis.log` => (`vndis`, `.log`)
+    fn split_path(&self) -> (&str, &str) {
+        match self.path.rfind('.') {
+            Some(pos) if !self.path[pos..].contains('/') => (&self.path[..pos], &self.path[pos..]),
+            _                                            => (&self.path[..], ""),
+        }
+    }
+
+    ///Moves current file to `<name>-YYYY-MM-DD[.N].log` and starts new one.
+    fn rotate(&mut self) {
+        let day = match self.day.take() {
+            Some(day) => day,
+            None      => return,
+        };
+
+        let new_path = {
+            let (stem, ext) = self.split_path();
+            let mut new_path = format!("{}-{}{}", stem, day, ext);
+            let mut num = 1;
+            while std::fs::metadata(&new_path).is_ok() {
+                new_path = format!("{}-{}.{}{}", stem, day, num, ext);
+                num += 1;
+            }
+            new_path
+        };
+
+        match std::fs::rename(&self.path, &new_path) {
+            Ok(_)    => {
+                self.fs_buf = FileStore::open_file(&self.path);
+                self.size = 0;
+                self.index.clear();
+                std::fs::rename(index_path(&self.path), index_path(&new_path)).unwrap_or(());
+            },
+            Err(err) => {
+                println!(">>>ERROR: Unable to rotate {}: {}", &self.path, err);
+                self.day = Some(day);
+            },
+        }
+    }
+
+    ///Returns rotated files sorted from the oldest to the newest ones.
+    ///
+    ///If `since` is specified, only files which may contain entries after it are returned.
+    fn rotated_files(&self, since: Option<i64>) -> Vec<String> {
+        let (stem, ext) = self.split_path();
+        let dir = match std::path::Path::new(stem).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _                                        => std::path::PathBuf::from("."),
+        };
+        let prefix = format!("{}-", std::path::Path::new(stem).file_name().and_then(|name| name.to_str()).unwrap_or(""));
+
+        let mut files: Vec<(String, u32, std::path::PathBuf)> = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries.filter_map(|entry| entry.ok()).filter_map(|entry| {
+                let name = match entry.file_name().into_string() {
+                    Ok(name) => name,
+                    Err(_)   => return None,
+                };
+                if !name.starts_with(&prefix) || !name.ends_with(ext) || name.len() < prefix.len() + ext.len() + 10 {
+                    return None;
+                }
+
+                //`YYYY-MM-DD` or `YYYY-MM-DD.N`
+                let middle = &name[prefix.len()..name.len() - ext.len()];
+                let (day, num) = match middle.find('.') {
+                    Some(pos) => (&middle[..pos], middle[pos + 1..].parse::<u32>().ok()),
+                    None      => (middle, Some(0)),
+                };
+
+                match (num, time::strptime(day, DAY_FORMAT)) {
+                    (Some(num), Ok(_)) if day.len() == 10 => Some((day.to_string(), num, entry.path())),
+                    _                                     => None,
+                }
+            }).collect(),
+            Err(_) => Vec::new(),
+        };
+        files.sort_by(|left, right| (&left.0, left.1).cmp(&(&right.0, right.1)));
+
+        //Days of files are local to time of writing, so compare with UTC day half a day earlier to be on the safe side.
+        let since = since.map(|since| local_day(since - 43200, 0));
+        //File contains entries until the next one starts.
+        let next_days: Vec<Option<String>> = files.iter().skip(1).map(|file| Some(file.0.clone())).chain(Some(self.day.clone())).collect();
+
+        files.into_iter().zip(next_days.into_iter())
+             .filter(|&(_, ref next_day)| match (&since, next_day) {
+                 (&Some(ref since), &Some(ref next_day)) => next_day >= since,
+                 _                                       => true,
+             })
+             .filter_map(|((_, _, path), _)| path.to_str().map(|path| path.to_string()))
+             .collect()
+    }
+
+    #[inline(always)]
+    fn open_file(path: &str) -> std::fs::File {
+        //Open file log once for both write/read.
+        //To read/write correctly be sure to .seek() at needed position
+        std::fs::OpenOptions::new().read(true)
+                                   .write(true)
+                                   .create(true)
+                                   .open(path)
+                                   .unwrap()
+    }
+
+    ///Writes entry to file, rotating it if needed.
+    fn write_entry(&mut self, entry: &IrcEntry) {
+        let day = local_day(entry.time, entry.utcoff);
+        let is_full = self.max_size > 0 && self.size >= self.max_size;
+        if self.day.as_ref().map_or(false, |current| *current != day || is_full) {
+            self.fs_buf.flush().unwrap();
+            self.rotate();
+        }
+
+        let line = format!("{}\n", entry.to_json());
+        let offset = self.fs_buf.seek(std::io::SeekFrom::End(0)).unwrap();
+        self.fs_buf.write_all(line.as_bytes()).unwrap();
+        self.size = offset + line.len() as u64;
+        self.update_index(entry.time, offset);
+        if self.day.is_none() {
+            self.day = Some(day);
+        }
+    }
+
+    ///Reads entries of files which may contain ones after `since`.
+    ///
+    ///Lines of both current and old formats are understood.
+    fn read_entries<F: FnMut(IrcEntry)>(&mut self, since: Option<i64>, mut func: F) {
+        fn read<R: BufRead, F: FnMut(IrcEntry)>(reader: R, func: &mut F) {
+            for entry in reader.lines().filter_map(|line| line.ok().as_ref().and_then(|line| IrcEntry::parse(line))) {
+                func(entry);
+            }
+        }
+
+        for path in self.rotated_files(since) {
+            let offset = index_offset(&load_index(&path), since);
+            match std::fs::File::open(&path).and_then(|mut file| file.seek(std::io::SeekFrom::Start(offset)).map(|_| file)) {
+                Ok(file) => read(std::io::BufReader::new(file), &mut func),
+                Err(err) => println!(">>>ERROR: Unable to read {}: {}", &path, err),
+            }
+        }
+
+        if self.fs_buf.metadata().unwrap().len() == 0 {
+            return;
+        }
+
+        //Entries are written in order, so start from the last indexed one before filter.
+        self.fs_buf.seek(std::io::SeekFrom::Start(index_offset(&self.index, since))).unwrap();
+        read(std::io::BufReader::new(&mut self.fs_buf), &mut func);
+    }
+}
+
+impl LogStore for FileStore {
+    fn append(&mut self, entries: &[IrcEntry]) {
+        for entry in entries {
+            self.write_entry(entry);
+        }
+        self.fs_buf.flush().unwrap()
+    }
+
+    fn range(&mut self, filter: &Filter) -> Vec<IrcEntry> {
+        let mut result = Vec::new();
+        self.read_entries(filter.since(), |entry| if filter.accepts(&entry) {
+            result.push(entry);
+        });
+        result
+    }
+
+    fn count(&mut self, filter: &Filter) -> usize {
+        let mut result = 0;
+        self.read_entries(filter.since(), |entry| if filter.accepts(&entry) {
+            result += 1;
+        });
+        result
+    }
+
+    ///Scans all files, so that words are matched as substrings.
+    fn search(&mut self, text: &str, limit: usize) -> Vec<IrcEntry> {
+        let words = search_words(text);
+        if words.is_empty() || limit == 0 {
+            return Vec::new();
+        }
+
+        let mut result = VecDeque::with_capacity(limit);
+        self.read_entries(None, |entry| if entry.contains_words(&words) {
+            if result.len() >= limit {
+                result.pop_front();
+            }
+            result.push_back(entry);
+        });
+        result.into_iter().collect()
+    }
+
+    ///Reads files backwards starting from the newest one.
+    fn last(&mut self, num: usize) -> Vec<IrcEntry> {
+        let mut result = read_last(&mut self.fs_buf, num);
+
+        for path in self.rotated_files(None).iter().rev() {
+            if result.len() >= num {
+                break;
+            }
+
+            if let Ok(mut file) = std::fs::File::open(path) {
+                let mut older = read_last(&mut file, num - result.len());
+                older.extend(result.drain(..));
+                result = older;
+            }
+        }
+
+        result
+    }
+
+    fn reopen(&mut self) {
+        self.fs_buf = FileStore::open_file(&self.path);
+        self.load_file_state();
+    }
+
+    #[inline(always)]
+    fn set_max_size(&mut self, max_size: u64) {
+        self.max_size = max_size;
+    }
 }
 
+///Log of channel.
+///
+///Recent entries are kept in memory and older ones are written to store.
 pub struct IrcLog {
     inner: VecDeque<IrcEntry>,
-    fs_buf: std::fs::File
+    ///Channel which entries belong to.
+    channel: String,
+    store: Box<LogStore>
 }
 
 impl IrcLog {
-    /// Creates log with default capacity 500.
+    /// Creates log with default capacity 500 over `vndis.log`.
     #[inline(always)]
     pub fn new() -> IrcLog {
+        IrcLog::open("vndis.log")
+    }
+
+    /// Creates log with default capacity 500 over specified file.
+    #[inline(always)]
+    pub fn open(path: &str) -> IrcLog {
+        IrcLog::with_channel(path, "")
+    }
+
+    /// Creates log of channel with default capacity 500 over specified file.
+    #[inline(always)]
+    pub fn with_channel(path: &str, channel: &str) -> IrcLog {
+        IrcLog::with_store(Box::new(FileStore::open(path)), channel)
+    }
+
+    /// Creates log of channel with default capacity 500 over specified store.
+    pub fn with_store(store: Box<LogStore>, channel: &str) -> IrcLog {
         IrcLog {
             inner: VecDeque::with_capacity(500),
-            //Open file log once for both write/read.
-            //To read/write correctly be sure to .seek() at needed position
-            fs_buf: std::fs::OpenOptions::new().read(true)
-                                               .write(true)
-                                               .create(true)
-                                               .open("vndis.log")
-                                               .unwrap()
+            channel: channel.to_string(),
+            store: store
         }
     }
 
+    /// Replaces store. Buffered entries are written to the new one.
+    #[inline(always)]
+    pub fn set_store(&mut self, store: Box<LogStore>) {
+        self.store = store;
+    }
+
+    #[inline(always)]
+    pub fn set_max_size(&mut self, max_size: u64) {
+        self.store.set_max_size(max_size);
+    }
+
+    /// Flushes buffer and reopens store e.g. after file is moved by logrotate.
+    pub fn reopen(&mut self) {
+        self.dump_to_file();
+        self.store.reopen();
+    }
+
     ///Dumps all logs except for last 20
     fn buff_to_file(&mut self) {
         let len = self.inner.len();
 
         if len <= 20 {return;}
 
-        self.fs_buf.seek(std::io::SeekFrom::End(0)).unwrap();
         //range is exclusive at the end
-        for _ in 0..len-19 {
-            self.fs_buf.write_fmt(format_args!("{}\n", self.inner.pop_front().unwrap())).unwrap();
-        }
-        self.fs_buf.flush().unwrap()
+        let entries: Vec<IrcEntry> = self.inner.drain(..len-19).collect();
+        self.store.append(&entries);
     }
 
-    ///Dumps all logs except for last 20
+    ///Dumps all logs
     fn dump_to_file(&mut self) {
-        let len = self.inner.len();
+        if self.inner.is_empty() {return;}
 
-        if len == 0 {return;}
+        let entries: Vec<IrcEntry> = self.inner.drain(..).collect();
+        self.store.append(&entries);
+    }
 
-        self.fs_buf.seek(std::io::SeekFrom::End(0)).unwrap();
-        //range is exclusive at the end
-        for _ in 0..len {
-            self.fs_buf.write_fmt(format_args!("{}\n", self.inner.pop_front().unwrap())).unwrap();
-        }
-        self.fs_buf.flush().unwrap()
+    #[inline(always)]
+    /// Writes all buffered entries to store.
+    pub fn flush(&mut self) {
+        self.dump_to_file();
     }
 
     #[inline(always)]
     /// Adds entry to log.
-    pub fn add(&mut self, entry: IrcEntry) {
+    ///
+    /// Entry without channel is assigned to channel of log.
+    pub fn add(&mut self, mut entry: IrcEntry) {
         if self.len() >= self.capacity() {
             self.buff_to_file();
         }
+        if entry.channel.is_empty() {
+            entry.channel = self.channel.clone();
+        }
         self.inner.push_back(entry);
     }
 
-    #[inline]
-    /// Reads all/filtered entries from underlying file buffer.
-    pub fn fs_read(&mut self, filter: &FilterLog) -> String {
-        if self.fs_buf.metadata().unwrap().len() == 0 {
-            return "".to_string();
-        }
+    #[inline(always)]
+    /// Reads all/filtered entries from underlying store.
+    pub fn fs_entries<F: Filter>(&mut self, filter: &F) -> Vec<IrcEntry> {
+        self.store.range(filter)
+    }
 
-        self.fs_buf.seek(std::io::SeekFrom::Start(0)).unwrap();
+    #[inline(always)]
+    /// Reads up to `num` last entries from underlying store.
+    pub fn fs_last(&mut self, num: usize) -> Vec<IrcEntry> {
+        self.store.last(num)
+    }
 
-        let reader = std::io::BufReader::new(&mut self.fs_buf);
-        let lines = reader.lines();
+    /// Returns up to `num` last entries of both heap buffer and store.
+    pub fn last(&mut self, num: usize) -> Vec<IrcEntry> {
+        let in_heap = std::cmp::min(num, self.len());
+        let mut result = if in_heap < num { self.fs_last(num - in_heap) } else { Vec::new() };
 
-        //Let's try to peek the number of lines for effective allocation
-        let acc_str = if let (_, Some(len)) = lines.size_hint() {
-            String::with_capacity(len*50)
+        result.extend(self.iter().skip(self.len() - in_heap).cloned());
+        result
+    }
+
+    /// Returns number of entries of both heap buffer and store.
+    pub fn count<F: Filter>(&mut self, filter: &F) -> usize {
+        self.store.count(filter) + self.iter().filter(|elem| filter.accepts(elem)).count()
+    }
+
+    /// Returns up to `limit` newest entries of both heap buffer and store which contain all words of `text`.
+    pub fn search(&mut self, text: &str, limit: usize) -> Vec<IrcEntry> {
+        let words = search_words(text);
+        if words.is_empty() {
+            return Vec::new();
         }
-        else {
-            String::new()
-        };
 
-        lines.fold(acc_str, |acc, line| {
-            const DATA_START: usize = 1;
-            const DATA_END: usize = 18;
-            let line = line.unwrap();
-            let time_stamp = time::strptime(&line[DATA_START..DATA_END], TIME_FORMAT).unwrap();
+        let mut in_heap: Vec<IrcEntry> = self.iter().rev().filter(|elem| elem.contains_words(&words)).take(limit).cloned().collect();
+        in_heap.reverse();
 
-            if filter.check(&time_stamp) {
-                acc + &format!("{}\n", line)
-            }
-            else {
-                acc
-            }
-        })
+        let mut result = if in_heap.len() < limit { self.store.search(text, limit - in_heap.len()) } else { Vec::new() };
+        result.extend(in_heap.into_iter());
+        result
+    }
+
+    #[inline]
+    /// Reads all/filtered entries from underlying file buffer in human readable format.
+    pub fn fs_read<F: Filter>(&mut self, filter: &F) -> String {
+        let entries = self.fs_entries(filter);
+
+        entries.iter().fold(String::with_capacity(entries.len()*50), |acc, item| acc + &format!("{}\n", item))
     }
 
     #[inline(always)]
@@ -158,7 +798,7 @@ impl IrcLog {
 
     #[inline(always)]
     /// Returns formatted string with log inner entries.
-    pub fn read_to_string(&self, filter: &FilterLog) -> String {
+    pub fn read_to_string<F: Filter>(&self, filter: &F) -> String {
         let log_size = self.len();
 
         if log_size == 0 {
@@ -166,14 +806,21 @@ impl IrcLog {
         }
         else {
             self.iter()
-                .filter(|elem| filter.check(&elem.time()))
+                .filter(|elem| filter.accepts(elem))
                 .fold(String::with_capacity(log_size*50), |acc, item| acc + &format!("{}\n", item))
         }
     }
 
+    ///Returns entries of file and heap buffers.
+    pub fn get_entries<F: Filter>(&mut self, filter: &F) -> Vec<IrcEntry> {
+        let mut result = self.fs_entries(filter);
+        result.extend(self.iter().filter(|elem| filter.accepts(elem)).cloned());
+        result
+    }
+
     #[inline(always)]
     ///Returns content of file and heap buffers.
-    pub fn get_all(&mut self, filter: &FilterLog) -> String {
+    pub fn get_all<F: Filter>(&mut self, filter: &F) -> String {
         format!("{}{}", self.fs_read(filter), self.read_to_string(filter))
     }
 
@@ -197,7 +844,7 @@ impl IrcLog {
     pub fn heap_size(&self) -> usize {
         self.iter().fold(0, |n, elem| n + elem.heap_size()) +
         std::mem::size_of::<VecDeque<IrcEntry>>() +
-        std::mem::size_of::<std::fs::File>()
+        std::mem::size_of::<Box<LogStore>>()
     }
 }
 
@@ -216,8 +863,24 @@ impl Drop for IrcLog {
 
 #[derive(Clone, Debug)]
 pub struct IrcEntry {
-    time: time::Tm,
+    ///Seconds since epoch.
+    time: i64,
+    ///UTC offset of local time when entry is made.
+    utcoff: i32,
+    kind: Kind,
+    channel: String,
+    ///Nickname of user who caused entry.
     nickname: String,
+    ///Hostmask `nick!user@host` of user, if known.
+    hostmask: Option<String>,
+    ///Text of entry which depends on kind:
+    ///
+    ///* `Join` - `user@host`;
+    ///* `Part`, `Quit` - reason;
+    ///* `Kick` - `<kicked nick> <reason>`;
+    ///* `Nick` - new nickname;
+    ///* `Topic` - new topic;
+    ///* `Mode` - modes with arguments.
     message: String
 }
 
@@ -225,18 +888,223 @@ impl IrcEntry {
     #[inline(always)]
     /// Creates new log entry from message and nick
     pub fn new(nick: String, msg: String) -> IrcEntry {
+        IrcEntry::with_kind(Kind::Message, nick, msg)
+    }
+
+    #[inline(always)]
+    /// Creates new log entry of specified kind
+    pub fn with_kind(kind: Kind, nick: String, msg: String) -> IrcEntry {
+        let now = time::get_time().sec;
+
         IrcEntry {
-            time: time::now(),
+            time: now,
+            utcoff: local_utcoff(now),
+            kind: kind,
+            channel: "".to_string(),
             nickname: nick,
+            hostmask: None,
             message: msg,
         }
     }
 
+    ///Parses entry from line of log file.
+    ///
+    ///Line is either JSON record or old `[<time>] <nick> message`.
+    pub fn parse(line: &str) -> Option<IrcEntry> {
+        if line.starts_with('{') {
+            IrcEntry::from_json(line)
+        }
+        else {
+            IrcEntry::parse_legacy(line)
+        }
+    }
+
+    ///Parses JSON record.
+    fn from_json(line: &str) -> Option<IrcEntry> {
+        let record: Record = match json::decode(line) {
+            Ok(record) => record,
+            Err(_)     => return None,
+        };
+
+        if record.v > FORMAT_VERSION {
+            return None;
+        }
+
+        match (time::strptime(&record.time, UTC_FORMAT), Kind::parse(&record.kind)) {
+            (Ok(time), Some(kind)) => Some(IrcEntry {
+                time: time.to_timespec().sec,
+                //Records without offset are displayed in current local time.
+                utcoff: record.utcoff.unwrap_or_else(|| local_utcoff(time.to_timespec().sec)),
+                kind: kind,
+                channel: record.channel,
+                nickname: record.nick,
+                hostmask: record.hostmask,
+                message: record.message
+            }),
+            _ => None,
+        }
+    }
+
+    ///Returns JSON record to write on disk.
+    pub fn to_json(&self) -> String {
+        let record = Record {
+            v: FORMAT_VERSION,
+            time: time::at_utc(time::Timespec::new(self.time, 0)).strftime(UTC_FORMAT).unwrap().to_string(),
+            utcoff: Some(self.utcoff),
+            channel: self.channel.clone(),
+            kind: self.kind.name().to_string(),
+            nick: self.nickname.clone(),
+            hostmask: self.hostmask.clone(),
+            message: self.message.clone()
+        };
+
+        json::encode(&record).unwrap()
+    }
+
+    ///Parses line of old format i.e. `[<time>] <nick> message`
+    fn parse_legacy(line: &str) -> Option<IrcEntry> {
+        const DATA_START: usize = 1;
+        const DATA_END: usize = 18;
+
+        if line.len() < DATA_END + 2 || !line.is_char_boundary(DATA_END) || !line[DATA_END..].starts_with("] ") {
+            return None;
+        }
+
+        let mut time = match time::strptime(&line[DATA_START..DATA_END], TIME_FORMAT) {
+            Ok(time) => time,
+            Err(_)   => return None,
+        };
+        //strptime() sets year as 15, while Tm would have 115.
+        if time.tm_year < 100 {
+            time.tm_year += 100;
+        }
+        //Log is written in local time.
+        //Non-zero offset makes to_timespec() use mktime(), which figures out whether DST is in effect at that time.
+        time.tm_utcoff = 1;
+        time.tm_isdst = -1;
+        let time = time.to_timespec().sec;
+
+        IrcEntry::parse_body(&line[DATA_END + 2..]).map(|(kind, nickname, message)| IrcEntry {
+            time: time,
+            utcoff: local_utcoff(time),
+            kind: kind,
+            channel: "".to_string(),
+            nickname: nickname.to_string(),
+            hostmask: None,
+            message: message
+        })
+    }
+
+    ///Parses entry without time stamp.
+    fn parse_body(body: &str) -> Option<(Kind, &str, String)> {
+        //Splits `<nick><end><message>` where message can be omitted.
+        fn split<'a>(text: &'a str, end: &str) -> Option<(&'a str, &'a str)> {
+            text.find(end).map(|pos| (&text[..pos], &text[pos + end.len()..]))
+                .or_else(|| if text.ends_with(end.trim_right()) { Some((&text[..text.len() - end.trim_right().len()], "")) } else { None })
+        }
+        //Strips brackets around reason.
+        fn reason(text: &str) -> Option<String> {
+            if text.starts_with('[') && text.ends_with(']') { Some(text[1..text.len() - 1].to_string()) } else { None }
+        }
+
+        if body.starts_with("-!- ") {
+            let body = &body[4..];
+
+            if body.starts_with("mode [") {
+                return body.rfind("] by ").map(|pos| (Kind::Mode, &body[pos + 5..], body[6..pos].to_string()));
+            }
+
+            let (nick, rest) = match body.find(' ') {
+                Some(pos) => (&body[..pos], &body[pos + 1..]),
+                None      => return None,
+            };
+
+            if rest.starts_with('[') && rest.ends_with("] has joined") {
+                Some((Kind::Join, nick, rest[1..rest.len() - 12].to_string()))
+            }
+            else if rest.starts_with("has left ") {
+                reason(&rest[9..]).map(|reason| (Kind::Part, nick, reason))
+            }
+            else if rest.starts_with("has quit ") {
+                reason(&rest[9..]).map(|reason| (Kind::Quit, nick, reason))
+            }
+            else if rest.starts_with("was kicked by ") {
+                split(&rest[14..], " ").and_then(|(by, rest)| reason(rest).map(|reason| (Kind::Kick, by, format!("{} {}", nick, reason))))
+            }
+            else if rest.starts_with("is now known as ") {
+                Some((Kind::Nick, nick, rest[16..].to_string()))
+            }
+            else if rest.starts_with("changed the topic to: ") {
+                Some((Kind::Topic, nick, rest[22..].to_string()))
+            }
+            else {
+                None
+            }
+        }
+        else if body.starts_with(" * ") {
+            split(&body[3..], " ").map(|(nick, message)| (Kind::Action, nick, message.to_string()))
+        }
+        else if body.starts_with('<') {
+            split(&body[1..], "> ").map(|(nick, message)| (Kind::Message, nick, message.to_string()))
+        }
+        else if body.starts_with('-') {
+            split(&body[1..], "- ").map(|(nick, message)| (Kind::Notice, nick, message.to_string()))
+        }
+        else {
+            None
+        }
+    }
+
     #[inline(always)]
+    ///Returns local time of entry.
     pub fn time(&self) -> time::Tm {
+        local_tm(self.time, self.utcoff)
+    }
+
+    #[inline(always)]
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    #[inline(always)]
+    pub fn channel(&self) -> &String {
+        &self.channel
+    }
+
+    #[inline(always)]
+    pub fn set_channel(&mut self, channel: String) {
+        self.channel = channel;
+    }
+
+    #[inline(always)]
+    pub fn hostmask(&self) -> Option<&String> {
+        self.hostmask.as_ref()
+    }
+
+    #[inline(always)]
+    pub fn set_hostmask(&mut self, hostmask: Option<String>) {
+        self.hostmask = hostmask;
+    }
+
+    #[inline(always)]
+    ///Returns time of entry in seconds.
+    pub fn timestamp(&self) -> i64 {
         self.time
     }
 
+    #[inline(always)]
+    ///Returns UTC offset of entry's local time in seconds.
+    pub fn utcoff(&self) -> i32 {
+        self.utcoff
+    }
+
+    #[inline(always)]
+    ///Sets time of entry in seconds and UTC offset of its local time.
+    pub fn set_time(&mut self, time: i64, utcoff: i32) {
+        self.time = time;
+        self.utcoff = utcoff;
+    }
+
     #[inline(always)]
     pub fn nickname(&self) -> &String {
         &self.nickname
@@ -247,16 +1115,38 @@ impl IrcEntry {
         &self.message
     }
 
+    ///Returns whether message contains all lowercase words.
+    pub fn contains_words(&self, words: &[String]) -> bool {
+        let message = self.message.to_lowercase();
+        words.iter().all(|word| message.contains(&word[..]))
+    }
+
 
     #[inline(always)]
     pub fn heap_size(&self) -> usize {
-        //there are 11 fields of i32 in Tm.
-        std::mem::size_of::<i32>() * 11 +
+        std::mem::size_of::<i64>() + std::mem::size_of::<i32>() +
+        utils::heap_size_of(self.channel().as_ptr() as *const c_void) +
         utils::heap_size_of(self.nickname().as_ptr() as *const c_void) +
+        self.hostmask().map_or(0, |hostmask| utils::heap_size_of(hostmask.as_ptr() as *const c_void)) +
         utils::heap_size_of(self.message().as_ptr() as *const c_void)
     }
 }
 
+///Record of log file.
+#[derive(RustcEncodable, RustcDecodable)]
+struct Record {
+    v: u32,
+    ///RFC 3339 time in UTC.
+    time: String,
+    ///UTC offset of local time in seconds.
+    utcoff: Option<i32>,
+    channel: String,
+    kind: String,
+    nick: String,
+    hostmask: Option<String>,
+    message: String
+}
+
 impl PartialEq for IrcEntry {
     fn eq(&self, right: &IrcEntry) -> bool {
         self.time == right.time
@@ -269,7 +1159,26 @@ impl PartialEq for IrcEntry {
 
 impl fmt::Display for IrcEntry {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(f, "[{}] <{}> {}", self.time.strftime(TIME_FORMAT).unwrap(), self.nickname, self.message)
+        try!(write!(f, "[{}] ", self.time().strftime(TIME_FORMAT).unwrap()));
+
+        match self.kind {
+            Kind::Message => write!(f, "<{}> {}", self.nickname, self.message),
+            Kind::Action  => write!(f, " * {} {}", self.nickname, self.message),
+            Kind::Notice  => write!(f, "-{}- {}", self.nickname, self.message),
+            Kind::Join    => write!(f, "-!- {} [{}] has joined", self.nickname, self.message),
+            Kind::Part    => write!(f, "-!- {} has left [{}]", self.nickname, self.message),
+            Kind::Quit    => write!(f, "-!- {} has quit [{}]", self.nickname, self.message),
+            Kind::Kick    => {
+                let (kicked, reason) = match self.message.find(' ') {
+                    Some(pos) => (&self.message[..pos], &self.message[pos + 1..]),
+                    None      => (&self.message[..], ""),
+                };
+                write!(f, "-!- {} was kicked by {} [{}]", kicked, self.nickname, reason)
+            },
+            Kind::Nick    => write!(f, "-!- {} is now known as {}", self.nickname, self.message),
+            Kind::Topic   => write!(f, "-!- {} changed the topic to: {}", self.nickname, self.message),
+            Kind::Mode    => write!(f, "-!- mode [{}] by {}", self.message, self.nickname),
+        }
     }
 }
 
@@ -279,6 +1188,10 @@ mod tests {
     use super::*;
     use std;
 
+    macro_rules! is_file {
+        ($path:expr) => { std::fs::metadata($path).ok().map_or(false, |data| data.is_file()); };
+    }
+
     #[test]
     fn test_irc_entry() {
         let entry = IrcEntry::new("Kuu".to_string(), "nya nya!".to_string());
@@ -287,26 +1200,293 @@ mod tests {
         assert!(entry.message == "nya nya!");
     }
 
+    #[test]
+    fn test_irc_entry_parse() {
+        let entry = IrcEntry::new("Kuu".to_string(), "nya nya!".to_string());
+        let parsed = IrcEntry::parse(&format!("{}", entry)).unwrap();
+
+        assert!(parsed.nickname == "Kuu");
+        assert!(parsed.message == "nya nya!");
+        assert!(parsed.timestamp() == entry.timestamp());
+
+        let parsed = IrcEntry::parse("[10/17/16 20:00:00] <Kuu> ").unwrap();
+        assert!(parsed.nickname == "Kuu");
+        assert!(parsed.message == "");
+        assert!(parsed.time().tm_year == 116);
+
+        assert!(IrcEntry::parse("").is_none());
+        assert!(IrcEntry::parse("[10/17/16 20:00:00] nya").is_none());
+    }
+
+    #[test]
+    fn test_irc_entry_kinds() {
+        let entries = [
+            (Kind::Message, "Kuu", "nya nya!", "<Kuu> nya nya!"),
+            (Kind::Action, "Kuu", "waves", " * Kuu waves"),
+            (Kind::Notice, "Kuu", "psst", "-Kuu- psst"),
+            (Kind::Join, "Kuu", "kuu@irc.net", "-!- Kuu [kuu@irc.net] has joined"),
+            (Kind::Part, "Kuu", "", "-!- Kuu has left []"),
+            (Kind::Quit, "Kuu", "Quit: bye [really]", "-!- Kuu has quit [Quit: bye [really]]"),
+            (Kind::Kick, "Douman", "Kuu too much nya", "-!- Kuu was kicked by Douman [too much nya]"),
+            (Kind::Nick, "Kuu", "Kuu_", "-!- Kuu is now known as Kuu_"),
+            (Kind::Topic, "Douman", "Visual novels", "-!- Douman changed the topic to: Visual novels"),
+            (Kind::Mode, "Douman", "+o Kuu", "-!- mode [+o Kuu] by Douman"),
+        ];
+
+        for &(kind, nick, message, expect) in entries.iter() {
+            let entry = IrcEntry::with_kind(kind, nick.to_string(), message.to_string());
+            let line = format!("{}", entry);
+            assert!(line.ends_with(&format!("] {}", expect)));
+
+            let parsed = IrcEntry::parse(&line).unwrap();
+            assert!(parsed.kind() == kind);
+            assert!(parsed.nickname == nick);
+            assert!(parsed.message == message);
+        }
+
+        assert!(IrcEntry::parse("[10/17/16 20:00:00] -!- Kuu does something").is_none());
+    }
+
+    #[test]
+    fn test_irc_entry_json() {
+        let mut entry = IrcEntry::with_kind(Kind::Topic, "Kuu".to_string(), "\"nya\"\t\x02nya".to_string());
+        entry.channel = "#vndis".to_string();
+        entry.set_hostmask(Some("Kuu!kuu@irc.net".to_string()));
+
+        let line = entry.to_json();
+        assert!(line.starts_with(&format!("{{\"v\":{},\"time\":\"", FORMAT_VERSION)));
+        assert!(!line.contains('\n'));
+
+        let parsed = IrcEntry::parse(&line).unwrap();
+        assert!(parsed.timestamp() == entry.timestamp());
+        assert!(parsed.utcoff() == entry.utcoff());
+        assert!(parsed.kind() == Kind::Topic);
+        assert!(parsed.channel() == "#vndis");
+        assert!(parsed.nickname() == "Kuu");
+        assert!(parsed.hostmask() == Some(&"Kuu!kuu@irc.net".to_string()));
+        assert!(parsed.message() == "\"nya\"\t\x02nya");
+        assert!(format!("{}", parsed) == format!("{}", entry));
+
+        let parsed = IrcEntry::parse(r#"{"v":1,"time":"2016-12-31T23:59:59Z","channel":"","kind":"message","nick":"Kuu","hostmask":null,"message":"nya"}"#).unwrap();
+        assert!(parsed.timestamp() == 1483228799);
+        assert!(parsed.hostmask().is_none());
+
+        assert!(IrcEntry::parse(r#"{"v":99,"time":"2016-12-31T23:59:59Z","channel":"","kind":"message","nick":"Kuu","hostmask":null,"message":"nya"}"#).is_none());
+        assert!(IrcEntry::parse(r#"{"v":1,"time":"12/31/16 23:59:59","channel":"","kind":"message","nick":"Kuu","hostmask":null,"message":"nya"}"#).is_none());
+        assert!(IrcEntry::parse("{nya").is_none());
+    }
+
+    #[test]
+    fn test_irc_log_legacy() {
+        use std::io::Write;
+        std::env::set_current_dir(std::env::current_exe().unwrap().parent().unwrap())
+                  .unwrap_or_else(|err| panic!("cannot enter my own directory :(. Err={}", err));
+
+        let filter = FilterLog::None;
+        {
+            let mut file = std::fs::File::create("legacy.log").unwrap();
+            file.write_all(b"[10/17/16 20:00:00] <Kuu> nya\n[10/17/16 20:00:05] -!- Kuu has quit [bye]\n").unwrap();
+        }
+
+        let mut log = IrcLog::with_channel("legacy.log", "#legacy");
+        let entry = IrcEntry::new("Douman".to_string(), "okaeri".to_string());
+        log.add(entry.clone());
+        log.flush();
+
+        let entries = log.get_entries(&filter);
+        assert!(entries.len() == 3);
+        assert!(entries[0].time().tm_year == 116);
+        assert!(entries[1].kind() == Kind::Quit);
+        assert!(entries[2].channel() == "#legacy");
+        assert!(log.fs_read(&filter) == format!("[10/17/16 20:00:00] <Kuu> nya\n[10/17/16 20:00:05] -!- Kuu has quit [bye]\n{}\n", entry));
+
+        drop(log);
+        std::fs::remove_file("legacy.log").unwrap();
+        std::fs::remove_file("legacy-2016-10-17.log").unwrap();
+        std::fs::remove_file("legacy.log.idx").unwrap_or(());
+    }
+
+    #[test]
+    fn test_irc_log_rotate() {
+        std::env::set_current_dir(std::env::current_exe().unwrap().parent().unwrap())
+                  .unwrap_or_else(|err| panic!("cannot enter my own directory :(. Err={}", err));
+
+        fn entry(time: i64, message: &str) -> IrcEntry {
+            let mut entry = IrcEntry::new("Kuu".to_string(), message.to_string());
+            entry.time = time;
+            entry.utcoff = 0;
+            entry
+        }
+
+        //2016-10-17 12:00:00 UTC
+        const DAY: i64 = 1476705600;
+        let mut store = FileStore::open("rotate.log");
+        store.append(&[entry(DAY, "first"), entry(DAY + 60, "second"), entry(DAY + 86400, "next day")]);
+
+        assert!(is_file!("rotate-2016-10-17.log"));
+        assert!(store.rotated_files(None) == vec!["./rotate-2016-10-17.log".to_string()]);
+        assert!(store.rotated_files(Some(DAY + 2 * 86400)).is_empty());
+
+        //Every entry goes to separate file once size limit is reached.
+        store.set_max_size(1);
+        store.append(&[entry(DAY + 86400 + 60, "big"), entry(DAY + 86400 + 120, "bigger")]);
+
+        assert!(is_file!("rotate-2016-10-18.log"));
+        assert!(is_file!("rotate-2016-10-18.1.log"));
+        assert!(store.rotated_files(None).len() == 3);
+
+        let messages = store.range(&FilterLog::None).iter().map(|entry| entry.message().clone()).collect::<Vec<_>>();
+        assert!(messages == vec!["first", "second", "next day", "big", "bigger"]);
+        let messages = store.range(&FilterLog::Last(DAY + 86400)).iter().map(|entry| entry.message().clone()).collect::<Vec<_>>();
+        assert!(messages == vec!["big", "bigger"]);
+        assert!(store.count(&FilterLog::Last(DAY + 86400)) == 2);
+
+        //Search goes through rotated files too.
+        let messages = store.search("BIG", 10).iter().map(|entry| entry.message().clone()).collect::<Vec<_>>();
+        assert!(messages == vec!["big", "bigger"]);
+        let messages = store.search("e", 2).iter().map(|entry| entry.message().clone()).collect::<Vec<_>>();
+        assert!(messages == vec!["next day", "bigger"]);
+        assert!(store.search("next nya", 10).is_empty());
+
+        //State of file is restored on open.
+        drop(store);
+        let mut store = FileStore::open("rotate.log");
+        assert!(store.day == Some("2016-10-18".to_string()));
+        store.append(&[entry(DAY + 2 * 86400, "another day")]);
+        assert!(is_file!("rotate-2016-10-18.2.log"));
+        assert!(store.rotated_files(Some(DAY + 2 * 86400)) == vec!["./rotate-2016-10-18.2.log".to_string()]);
+
+        drop(store);
+        for path in ["rotate.log", "rotate-2016-10-17.log", "rotate-2016-10-18.log", "rotate-2016-10-18.1.log", "rotate-2016-10-18.2.log"].iter() {
+            std::fs::remove_file(path).unwrap();
+            std::fs::remove_file(format!("{}.idx", path)).unwrap_or(());
+        }
+    }
+
+    #[test]
+    fn test_irc_log_index() {
+        std::env::set_current_dir(std::env::current_exe().unwrap().parent().unwrap())
+                  .unwrap_or_else(|err| panic!("cannot enter my own directory :(. Err={}", err));
+
+        //2016-10-17 00:00:00 UTC
+        const DAY: i64 = 1476662400;
+        let mut store = FileStore::open("index.log");
+        store.index_step = 1000;
+
+        let entries = (0..200).map(|idx| {
+            let mut entry = IrcEntry::new("Kuu".to_string(), format!("i={}", idx));
+            entry.time = DAY + idx * 60;
+            entry.utcoff = 0;
+            entry
+        }).collect::<Vec<_>>();
+        store.append(&entries);
+
+        assert!(store.index.len() > 10);
+        assert!(store.index[0] == (DAY, 0));
+        assert!(store.index.windows(2).all(|points| points[1].1 - points[0].1 >= 1000));
+        assert!(load_index("index.log") == store.index);
+
+        //Seek goes to the last point before filter.
+        let offset = index_offset(&store.index, Some(DAY + 150 * 60));
+        assert!(offset > 0);
+        assert!(index_offset(&store.index, Some(DAY - 1)) == 0);
+        assert!(index_offset(&store.index, None) == 0);
+
+        let entries = store.range(&FilterLog::Last(DAY + 150 * 60));
+        assert!(entries.len() == 49);
+        assert!(entries[0].message() == "i=151");
+
+        //Backward read.
+        let last = store.last(3);
+        assert!(last.iter().map(|entry| entry.message().clone()).collect::<Vec<_>>() == vec!["i=197", "i=198", "i=199"]);
+        let last = store.last(500);
+        assert!(last.len() == 200);
+        assert!(last[0].message() == "i=0");
+
+        //Stale index is dropped on reopen.
+        std::fs::File::create("index.log").unwrap();
+        store.reopen();
+        assert!(store.index.is_empty());
+        assert!(load_index("index.log").is_empty());
+
+        drop(store);
+        std::fs::remove_file("index.log").unwrap();
+        std::fs::remove_file("index.log.idx").unwrap();
+    }
+
+    #[test]
+    fn test_kind_filter() {
+        let all = KindFilter::all();
+        assert!(KINDS.iter().all(|kind| all.check(*kind)));
+        assert!(format!("{}", all) == "All");
+        assert!(KindFilter::parse(&[]) == Ok(all));
+
+        let only = KindFilter::parse(&["+join", "PART"]).unwrap();
+        assert!(only.check(Kind::Join) && only.check(Kind::Part));
+        assert!(!only.check(Kind::Message));
+        assert!(format!("{}", only) == "[join,part]");
+
+        let except = KindFilter::parse(&["-mode", "-nick"]).unwrap();
+        assert!(except.check(Kind::Message));
+        assert!(!except.check(Kind::Mode) && !except.check(Kind::Nick));
+
+        assert!(KindFilter::parse(&["+nya"]).is_err());
+
+        let entry = IrcEntry::with_kind(Kind::Mode, "Douman".to_string(), "+o Kuu".to_string());
+        assert!(!(FilterLog::None, except).accepts(&entry));
+        assert!((FilterLog::None, all).accepts(&entry));
+    }
+
     #[test]
     fn test_filter_log() {
-        let time_now    = time::now();
-        let time_before = time_now - time::Duration::minutes(10);
-        let time_before = time_before.to_local();
-        let time_after  = time_now + time::Duration::minutes(10);
-        let time_after  = time_after.to_local();
+        let time_now    = time::get_time().sec;
+        let time_before = time_now - 600;
+        let time_after  = time_now + 600;
         let no_filter   = FilterLog::None;
         let filter_now  = FilterLog::Last(time_now);
 
-        assert!(no_filter.check(&time_before));
+        assert!(no_filter.check(time_before));
         assert!(format!("{}", no_filter) == "None");
 
-        assert!(!filter_now.check(&time_before));
-        assert!(filter_now.check(&time_after));
-        assert!(format!("{}", filter_now) == format!("Last({})", time_now.strftime(super::TIME_FORMAT).unwrap()));
+        assert!(!filter_now.check(time_before));
+        assert!(filter_now.check(time_after));
+        assert!(format!("{}", filter_now) == format!("Last({})", time::at(time::Timespec::new(time_now, 0)).strftime(super::TIME_FORMAT).unwrap()));
     }
 
-    macro_rules! is_file {
-        ($path:expr) => { std::fs::metadata($path).ok().map_or(false, |data| data.is_file()); };
+    #[test]
+    fn test_filter_log_edges() {
+        fn entry(time: i64, utcoff: i32) -> IrcEntry {
+            let mut entry = IrcEntry::new("Kuu".to_string(), "nya".to_string());
+            entry.time = time;
+            entry.utcoff = utcoff;
+            entry
+        }
+
+        //End of DST in Europe: 02:30 CEST is earlier than 02:15 CET.
+        let summer = entry(1477787400, 7200);
+        let winter = entry(1477790100, 3600);
+        assert!(summer.time().tm_hour == 2 && summer.time().tm_min == 30);
+        assert!(winter.time().tm_hour == 2 && winter.time().tm_min == 15);
+        assert!(FilterLog::Last(summer.timestamp()).accepts(&winter));
+        assert!(!FilterLog::Last(winter.timestamp()).accepts(&summer));
+
+        //Local time is already in new year, while UTC is not yet.
+        let new_year = entry(1483227000, 3600);
+        assert!(new_year.time().tm_year == 117 && new_year.time().tm_mon == 0 && new_year.time().tm_mday == 1);
+        assert!(!FilterLog::Last(1483228800).accepts(&new_year));
+        assert!(FilterLog::Last(1483227000 - 1).accepts(&new_year));
+        assert!(format!("{}", new_year).starts_with("[01/01/17 00:30:00]"));
+
+        //Month boundary.
+        let february = entry(1485907200, 0);
+        assert!(FilterLog::Last(1485903600).accepts(&february));
+        assert!(!FilterLog::Last(1485907200).accepts(&february));
+
+        //Offset is kept on disk.
+        let parsed = IrcEntry::parse(&summer.to_json()).unwrap();
+        assert!(parsed.timestamp() == summer.timestamp());
+        assert!(parsed.utcoff() == 7200);
+        assert!(format!("{}", parsed) == format!("{}", summer));
     }
 
     #[test]
@@ -345,8 +1525,41 @@ mod tests {
         assert!(log.back().unwrap().nickname == format!("Kuu{}", old_capacity));
         assert!(log.back().unwrap().message == format!("i={}", old_capacity));
         assert!(log.get_all(&filter) == expect_str);
+        assert!(log.count(&filter) == old_capacity + 3);
+
+        //Search covers both file and heap buffer.
+        let found = log.search("NYA", 10);
+        assert!(found.len() == 1 && found[0].message() == "nya nya!");
+        let found = log.search(&format!("i={}", old_capacity), 1);
+        assert!(found.len() == 1 && found[0].message() == log.back().unwrap().message());
+        assert!(log.search("  ", 10).is_empty());
 
         drop(log);
         std::fs::remove_file("vndis.log").unwrap();
+        std::fs::remove_file("vndis.log.idx").unwrap_or(());
+    }
+
+    #[test]
+    fn test_irc_log_reopen() {
+        std::env::set_current_dir(std::env::current_exe().unwrap().parent().unwrap())
+                  .unwrap_or_else(|err| panic!("cannot enter my own directory :(. Err={}", err));
+
+        let filter = FilterLog::None;
+        let mut log = IrcLog::open("reopen.log");
+        let entry = IrcEntry::new("Kuu".to_string(), "nya nya!".to_string());
+        log.add(entry.clone());
+
+        //Emulate logrotate
+        std::fs::rename("reopen.log", "reopen.log.1").unwrap();
+        log.reopen();
+
+        assert!(is_file!("reopen.log"));
+        assert!(log.len() == 0);
+        assert!(log.fs_read(&filter).is_empty());
+
+        drop(log);
+        std::fs::remove_file("reopen.log").unwrap();
+        std::fs::remove_file("reopen.log.1").unwrap();
+        std::fs::remove_file("reopen.log.idx").unwrap_or(());
     }
 }
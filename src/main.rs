@@ -4,26 +4,45 @@ extern crate hyper;
 extern crate url;
 extern crate time;
 extern crate regex;
+extern crate rustc_serialize;
 
 use irc::client::prelude::*;
 use irc::client::conn::NetStream;
 use std::io::{Write, Read, BufWriter, BufReader};
+use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use log::LogStore;
 mod utils;
 mod log;
+mod commands;
+mod channel;
+mod acl;
+mod identity;
+mod queue;
+mod signal;
+mod reconnect;
+mod nick;
+mod roster;
+mod seen;
+mod memo;
+mod scheduler;
+mod greeting;
+mod sqlite;
 
 #[cfg(test)]
 const GITHUB_AUTH: &'static str = "some_token";
 #[cfg(not(test))]
 const GITHUB_AUTH: &'static str = include_str!("github_token.txt");
 
-const VNDIS: &'static str  = "#vndis";
 const MASTER: &'static str = "Douman";
-const USAGE: &'static str  = "Available commands:\n
-ping      - to get pong in response. Available via !\n
-grep <vn> - to get search link on vndb\n
-google    - to get search link on google\n
-log <cmd> - access to log facilities. See log help for more information. Available via !\n
-help      - to get this message";
+const CONFIG_PATH: &'static str = "config.json";
+const SEEN_PATH: &'static str = "seen.idx";
+const MEMO_PATH: &'static str = "memos.txt";
+const REMINDER_PATH: &'static str = "reminders.txt";
+const GREETING_PATH: &'static str = "greetings.txt";
+const PRIVATE_LOG_PATH: &'static str = "private.log";
+///Max number of entries found by log search.
+const LOG_SEARCH_LIMIT: usize = 20;
 
 ///Represents bot responses
 #[derive(Debug)]
@@ -32,18 +51,65 @@ enum BotResponse {
     Private(String),
     PrivateMulti(String),
     Channel(String),
-    DyingMsg
+    DyingMsg,
+    ///Sender's identity should be verified before response.
+    Unverified
 }
 
 struct KuuBot {
     server: IrcServer<BufReader<NetStream>, BufWriter<NetStream>>,
     nick: String,
-    joined: bool,
+    ///Own full prefix `nick!user@host`
+    hostmask: String,
+    commands: commands::Registry,
+    acl: acl::Acl,
+    identities: RefCell<identity::Identities>,
+    verify_method: identity::Method,
+    ///Whether server sends IRCv3 account tag.
+    account_tag: bool,
+    outbox: queue::Outbox,
+    ///Marker to put at the end of split message.
+    continuation: String,
+    quit_message: String,
+    ///Set when bot is requested to shut down.
+    stopping: Cell<bool>,
+    ///Currently applied config.
+    config: Config,
+    ///Owner who requested to reload config.
+    reload_requester: RefCell<Option<String>>,
+    backoff: reconnect::Backoff,
+    ///Delay before rejoining channel after kick or failed join.
+    rejoin_delay_ms: u64,
+    ///Max number of failed attempts to rejoin.
+    rejoin_attempts: u32,
+    ///Earliest time(in seconds) when some channel should be rejoined.
+    next_rejoin: Cell<Option<i64>>,
+    nicks: nick::Nicks,
+    ///NickServ command to take primary nickname back i.e. GHOST or REGAIN.
+    regain_method: Option<String>,
+    seen: RefCell<seen::SeenIndex>,
+    memos: RefCell<memo::Memos>,
+    ///Whether memos are delivered by private message instead of channel.
+    memo_private: bool,
+    scheduler: RefCell<scheduler::Scheduler>,
+    waker: scheduler::Waker,
+    greetings: RefCell<greeting::Greetings>,
+    ///Outgoing messages which are not logged yet as pairs of target and text.
+    outgoing: RefCell<Vec<(String, String)>>,
+    ///Whether own messages are logged on channels.
+    log_own: bool,
+    ///Whether own private messages are logged into separate log.
+    log_own_private: bool,
+    private_log: RefCell<Option<log::IrcLog>>,
+    ///Size of log file in bytes after which it is rotated.
+    log_max_size: u64,
+    ///Store of channel logs.
+    log_backend: log::Backend,
 }
 
 impl std::fmt::Display for KuuBot {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
-        write!(f, "KuuBot(nick={}, joined={})", self.nick, self.joined)
+        write!(f, "KuuBot(nick={}) {}", self.nick, self.backoff)
     }
 }
 
@@ -51,126 +117,797 @@ impl KuuBot {
     #[inline(always)]
     ///Creates default bot.
     fn new() -> KuuBot {
-        KuuBot {
-            server: IrcServer::new("config.json").unwrap(),
+        let server = IrcServer::new(CONFIG_PATH).unwrap();
+        let config = server.config().clone();
+
+        let mut bot = KuuBot {
+            server: server,
             nick: "".to_string(),
-            joined: false,
+            hostmask: "".to_string(),
+            commands: commands::Registry::with_defaults(),
+            acl: acl::Acl::new(),
+            identities: RefCell::new(identity::Identities::new()),
+            verify_method: identity::Method::Whois,
+            account_tag: false,
+            outbox: queue::Outbox::new(4.0, 0.5),
+            continuation: "".to_string(),
+            quit_message: "...".to_string(),
+            stopping: Cell::new(false),
+            config: config.clone(),
+            reload_requester: RefCell::new(None),
+            backoff: reconnect::Backoff::new(1000, 300000, 0),
+            rejoin_delay_ms: 5000,
+            rejoin_attempts: 3,
+            next_rejoin: Cell::new(None),
+            nicks: nick::Nicks::new("", Vec::new(), 0),
+            regain_method: None,
+            seen: RefCell::new(seen::SeenIndex::open(SEEN_PATH)),
+            memos: RefCell::new(memo::Memos::open(MEMO_PATH, 5)),
+            memo_private: false,
+            scheduler: RefCell::new(scheduler::Scheduler::open(REMINDER_PATH, 10)),
+            waker: scheduler::Waker::new(),
+            greetings: RefCell::new(greeting::Greetings::open(GREETING_PATH, 3600)),
+            outgoing: RefCell::new(Vec::new()),
+            log_own: true,
+            log_own_private: false,
+            private_log: RefCell::new(None),
+            log_max_size: 10485760,
+            log_backend: log::Backend::File,
+        };
+
+        bot.apply_config(&config);
+        bot
+    }
+
+    ///Applies bot's settings from config.
+    fn apply_config(&mut self, config: &Config) {
+        self.acl = acl::Acl::from_config(config);
+        self.verify_method = utils::get_option(config, "identify_method").and_then(|method| identity::Method::parse(method))
+                                                                         .unwrap_or(identity::Method::Whois);
+        self.continuation = utils::get_option(config, "continuation_marker").map_or("".to_string(), |marker| marker.clone());
+        self.quit_message = utils::get_option(config, "quit_message").map_or("...".to_string(), |message| message.clone());
+
+        //Empty bucket which is never refilled would stall all outgoing messages.
+        let flood_option = |name: &str, default: f64, min: f64| {
+            let value = utils::get_option(config, name).and_then(|value| value.parse::<f64>().ok())
+                                                       .unwrap_or(default);
+            if value.is_finite() && value >= min {
+                value
+            }
+            else {
+                println!(">>>ERROR: Option {}={} is invalid. Use {}", name, value, min);
+                min
+            }
+        };
+        self.outbox.set_rate(flood_option("flood_burst", 4.0, 1.0), flood_option("flood_refill", 0.5, 0.1));
+
+        let num_option = |name: &str, default: u64| utils::get_option(config, name).and_then(|value| value.parse::<u64>().ok())
+                                                                                   .unwrap_or(default);
+        self.backoff.set_limits(num_option("reconnect_base_ms", 1000),
+                                num_option("reconnect_max_ms", 300000),
+                                num_option("reconnect_attempts", 0) as u32);
+        self.rejoin_delay_ms = num_option("rejoin_delay_ms", 5000);
+        self.rejoin_attempts = num_option("rejoin_attempts", 3) as u32;
+
+        self.memos.borrow_mut().set_limit(num_option("memo_limit", 5) as usize);
+        self.scheduler.borrow_mut().set_limit(num_option("reminder_limit", 10) as usize);
+        self.greetings.borrow_mut().set_cooldown(num_option("greet_cooldown", 3600) as i64);
+        self.memo_private = utils::get_option(config, "memo_delivery").map_or(false, |delivery| delivery == "private");
+        //Own messages are logged on channels only by default.
+        let log_own = utils::get_option(config, "log_own").map_or("channel".to_string(), |log_own| log_own.to_lowercase());
+        self.log_own = log_own != "off";
+        self.log_own_private = log_own == "all";
+        self.log_max_size = num_option("log_max_size", 10485760);
+        self.log_backend = utils::get_option(config, "log_backend").and_then(|backend| log::Backend::parse(&backend.to_lowercase()))
+                                                                   .unwrap_or(log::Backend::File);
+
+        self.nicks.set(config.nickname(), config.alt_nicks.clone().unwrap_or(Vec::new()), num_option("regain_interval", 300) as i64);
+        self.regain_method = match utils::get_option(config, "regain_method").map(|method| method.to_uppercase()) {
+            Some(ref method) if method == "GHOST" || method == "REGAIN" => Some(method.clone()),
+            _                                                          => None,
+        };
+    }
+
+    ///Returns human readable list of differences between configs.
+    fn config_changes(old: &Config, new: &Config) -> Vec<String> {
+        let mut changes = Vec::new();
+
+        if old.owners != new.owners {
+            changes.push(format!("owners: {:?} => {:?}", old.owners.as_ref().unwrap_or(&Vec::new()),
+                                                         new.owners.as_ref().unwrap_or(&Vec::new())));
+        }
+
+        let no_options = HashMap::new();
+        let old_options = old.options.as_ref().unwrap_or(&no_options);
+        let new_options = new.options.as_ref().unwrap_or(&no_options);
+        let mut keys: Vec<&String> = old_options.keys().chain(new_options.keys()).collect();
+        keys.sort();
+        keys.dedup();
+
+        for key in keys {
+            match (old_options.get(key), new_options.get(key)) {
+                (Some(old), Some(new)) if old != new => changes.push(format!("{}: {} => {}", key, old, new)),
+                (None, Some(new))                    => changes.push(format!("{}: set to {}", key, new)),
+                (Some(old), None)                    => changes.push(format!("{}: {} is removed", key, old)),
+                _                                    => (),
+            }
+        }
+
+        if old.nickname != new.nickname || old.server != new.server || old.port != new.port || old.use_ssl != new.use_ssl {
+            changes.push("server or nickname is changed, but it requires restart".to_string());
+        }
+
+        changes
+    }
+
+    ///Reopens log files and re-reads config.
+    ///
+    ///New config is compared to running state, so that channels are joined or left as needed.
+    ///Returns list of changes or error if config cannot be loaded.
+    fn reload(&mut self, channels: &mut HashMap<String, channel::Channel>) -> Result<Vec<String>, String> {
+        for chan in channels.values_mut() {
+            chan.log.reopen();
+        }
+        if let Some(ref mut private_log) = *self.private_log.borrow_mut() {
+            private_log.reopen();
+        }
+        self.seen.borrow_mut().save();
+
+        let config = try!(Config::load(CONFIG_PATH).map_err(|err| format!("{} is not valid: {}", CONFIG_PATH, err)));
+        let mut changes = KuuBot::config_changes(&self.config, &config);
+
+        let new_channels: Vec<String> = config.channels().iter().map(|name| name.to_string()).collect();
+        let new_keys: Vec<String> = new_channels.iter().map(|name| channel::key(name)).collect();
+        let joined: Vec<String> = new_channels.iter().filter(|name| !channels.contains_key(&channel::key(name))).cloned().collect();
+        let left: Vec<String> = channels.iter().filter(|&(key, _)| !new_keys.contains(key)).map(|(_, chan)| chan.name.clone()).collect();
+
+        for name in joined {
+            self.send("", Message::new(None, "JOIN", Some(vec![&name[..]]), None), queue::Priority::Normal);
+            channels.insert(channel::key(&name), channel::Channel::with_backend(&name, self.log_backend));
+            changes.push(format!("join {}", name));
+        }
+
+        for name in left {
+            self.send("", Message::new(None, "PART", Some(vec![&name[..]]), None), queue::Priority::Normal);
+            if let Some(mut chan) = channels.remove(&channel::key(&name)) {
+                chan.log.flush();
+            }
+            changes.push(format!("leave {}", name));
+        }
+
+        let old_backend = self.log_backend;
+        self.apply_config(&config);
+        self.config = config;
+        for chan in channels.values_mut() {
+            if self.log_backend != old_backend {
+                chan.set_backend(self.log_backend);
+            }
+            chan.log.set_max_size(self.log_max_size);
+        }
+        Ok(changes)
+    }
+
+    ///Reloads config and reports result to owner who requested it.
+    fn reload_report(&mut self, channels: &mut HashMap<String, channel::Channel>) {
+        let report = match self.reload(channels) {
+            Ok(ref changes) if changes.is_empty() => "Config is reloaded. Nothing is changed".to_string(),
+            Ok(changes)                           => format!("Config is reloaded:\n{}", changes.join("\n")),
+            Err(error)                            => format!("Config is not reloaded: {}", error),
+        };
+        println!(">>>{}", report);
+
+        if let Some(nickname) = self.reload_requester.borrow_mut().take() {
+            self.send_response(BotResponse::PrivateMulti(report), &nickname, &nickname, acl::Role::Owner);
+        }
+    }
+
+    ///Reconnects bot with exponential backoff.
+    ///
+    ///Returns false if bot should stop instead i.e. failure budget is exhausted or stop is requested.
+    ///Signals cut the wait short, so that bot stops or reloads config without delay.
+    fn reconnect(&mut self, channels: &mut HashMap<String, channel::Channel>) -> bool {
+        self.account_tag = false;
+        self.identities.borrow_mut().clear();
+        self.nicks.reset();
+
+        loop {
+            if self.backoff.is_exhausted() {
+                println!(">>>ERROR: Giving up on reconnect. {}", self.backoff);
+                return false;
+            }
+
+            //Jitter spreads reconnects of several bots, so fraction of nanoseconds is random enough.
+            let delay = self.backoff.next_delay((time::precise_time_ns() % 1000) as f64 / 1000.0);
+            println!(">>>Reconnect in {}ms. {}", delay, self.backoff);
+            std::io::stdout().flush().unwrap();
+            signal::sleep(delay);
+
+            if signal::is_stop() {
+                return false;
+            }
+            if signal::is_reload() {
+                self.reload_report(channels);
+            }
+
+            match self.server.reconnect().and_then(|_| self.server.identify()) {
+                Ok(_) => {
+                    self.backoff.connected();
+                    self.request_caps();
+                    return true;
+                },
+                Err(err) => {
+                    println!(">>>ERROR: Unable to reconnect: {}", err);
+                    self.backoff.failure(format!("{}", err));
+                }
+            }
+        }
+    }
+
+    ///Joins channel after configured delay.
+    ///
+    ///JOIN is sent by main loop, so that channel which is left or joined meanwhile is skipped.
+    fn schedule_join(&self, chan: &mut channel::Channel) {
+        let at = time::get_time().sec + ((self.rejoin_delay_ms + 999) / 1000) as i64;
+        chan.rejoin_at = Some(at);
+
+        self.next_rejoin.set(Some(self.next_rejoin.get().map_or(at, |next| std::cmp::min(next, at))));
+        self.schedule_wake();
+    }
+
+    ///Joins channels which are due to rejoin.
+    fn rejoin_channels(&self, channels: &mut HashMap<String, channel::Channel>) {
+        let now = time::get_time().sec;
+        match self.next_rejoin.get() {
+            Some(next) if next <= now => (),
+            _                         => return,
+        }
+
+        for chan in channels.values_mut() {
+            if chan.should_rejoin(now) {
+                self.send("", Message::new(None, "JOIN", Some(vec![&chan.name[..]]), None), queue::Priority::Normal);
+            }
+        }
+
+        self.next_rejoin.set(channels.values().filter_map(|chan| chan.rejoin_at).min());
+        self.schedule_wake();
+    }
+
+    ///Wakes main loop up for the next reminder or rejoin, whichever comes first.
+    fn schedule_wake(&self) {
+        let next = match (self.scheduler.borrow().next_time(), self.next_rejoin.get()) {
+            (Some(reminder), Some(rejoin)) => Some(std::cmp::min(reminder, rejoin)),
+            (reminder, rejoin)             => reminder.or(rejoin),
+        };
+        self.waker.set(next);
+    }
+
+    ///Sends private message to each owner who is configured by nickname.
+    fn notify_owners(&self, text: &str) {
+        if let Some(ref owners) = self.config.owners {
+            for owner in owners.iter().filter(|owner| !owner.contains('!') && !owner.contains('@')) {
+                self.queue_msg(owner, text, queue::Priority::High);
+            }
+        }
+    }
+
+    ///Writes channel events into logs.
+    ///
+    ///Must be called before rosters are updated as QUIT and NICK are logged on channels where user is present.
+    fn log_event(&self, message: &Message, channels: &mut HashMap<String, channel::Channel>) {
+        let nickname = match utils::get_nick(&message.prefix) {
+            Some(ref nickname) if nickname.is_empty() => message.prefix.clone().unwrap_or("".to_string()),
+            Some(nickname) => nickname,
+            None => return,
+        };
+        let target = message.args.get(0).or(message.suffix.as_ref()).cloned().unwrap_or("".to_string());
+        let suffix = message.suffix.clone().unwrap_or("".to_string());
+
+        let (kind, text) = match &message.command[..] {
+            "JOIN"   => (log::Kind::Join, message.prefix.as_ref().and_then(|prefix| prefix.find('!').map(|pos| prefix[pos + 1..].to_string()))
+                                                            .unwrap_or("".to_string())),
+            //Reason is suffix only when channel is in arguments.
+            "PART"   => (log::Kind::Part, if message.args.is_empty() { "".to_string() } else { suffix }),
+            "KICK"   => (log::Kind::Kick, format!("{} {}", message.args.get(1).map_or("", |kicked| &kicked[..]), suffix)),
+            "TOPIC"  => (log::Kind::Topic, suffix),
+            "MODE"   => {
+                let mut params: Vec<&str> = message.args.iter().skip(1).map(|arg| &arg[..]).collect();
+                params.extend(suffix.split_whitespace());
+                (log::Kind::Mode, params.join(" "))
+            },
+            "NOTICE" => (log::Kind::Notice, suffix),
+            "QUIT" | "NICK" => {
+                let (kind, text) = if message.command == "QUIT" {
+                    (log::Kind::Quit, suffix)
+                }
+                else {
+                    (log::Kind::Nick, target)
+                };
+
+                for chan in channels.values_mut().filter(|chan| chan.roster.get(&nickname).is_some()) {
+                    let mut entry = log::IrcEntry::with_kind(kind, nickname.clone(), text.clone());
+                    entry.set_hostmask(message.prefix.clone());
+                    chan.log.add(entry);
+                    println!("{}", chan.log.back().unwrap());
+                }
+                return;
+            },
+            _ => return,
+        };
+
+        if let Some(chan) = channels.get_mut(&channel::key(&target)) {
+            let mut entry = log::IrcEntry::with_kind(kind, nickname, text);
+            entry.set_hostmask(message.prefix.clone());
+            chan.log.add(entry);
+            println!("{}", chan.log.back().unwrap());
+        }
+    }
+
+    ///Keeps rosters of channels up to date.
+    fn track_members(&self, message: &Message, channels: &mut HashMap<String, channel::Channel>) {
+        let nickname = utils::get_nick(&message.prefix).unwrap_or("".to_string());
+        //Channel is either first argument or suffix e.g. `JOIN :#vndis`
+        let target = message.args.get(0).or(message.suffix.as_ref()).cloned().unwrap_or("".to_string());
+
+        match &message.command[..] {
+            "JOIN" => if let Some(chan) = channels.get_mut(&channel::key(&target)) {
+                //Own JOIN is followed by NAMES.
+                if nickname.to_lowercase() == self.nick.to_lowercase() {
+                    chan.roster.clear();
+                }
+                chan.roster.join(message.prefix.as_ref().unwrap_or(&nickname), time::get_time().sec);
+                self.seen.borrow_mut().update(&nickname, seen::Event::Join, &chan.name, None, time::get_time().sec);
+            },
+            "PART" => if let Some(chan) = channels.get_mut(&channel::key(&target)) {
+                chan.roster.remove(&nickname);
+                self.seen.borrow_mut().update(&nickname, seen::Event::Part, &chan.name, None, time::get_time().sec);
+            },
+            "KICK" => if let (Some(chan), Some(kicked)) = (channels.get_mut(&channel::key(&target)), message.args.get(1)) {
+                chan.roster.remove(kicked);
+            },
+            "QUIT" => {
+                for chan in channels.values_mut() {
+                    chan.roster.remove(&nickname);
+                }
+                self.seen.borrow_mut().update(&nickname, seen::Event::Quit, "", None, time::get_time().sec);
+                if message.suffix.as_ref().map_or(false, |reason| greeting::is_netsplit(reason)) {
+                    self.greetings.borrow_mut().split(&nickname, time::get_time().sec);
+                }
+            },
+            "NICK" => for chan in channels.values_mut() {
+                chan.roster.rename(&nickname, &target);
+            },
+            "MODE" => if let Some(chan) = channels.get_mut(&channel::key(&target)) {
+                let mut params: Vec<&str> = message.args.iter().skip(1).map(|arg| &arg[..]).collect();
+                if let Some(ref suffix) = message.suffix {
+                    params.extend(suffix.split_whitespace());
+                }
+
+                if !params.is_empty() {
+                    chan.roster.mode(params[0], &params[1..]);
+                }
+            },
+            //RPL_NAMREPLY
+            "353" => if let Some(chan) = message.args.get(2).and_then(|name| channels.get_mut(&channel::key(name))) {
+                chan.roster.names(message.suffix.as_ref().map_or("", |names| &names[..]));
+            },
+            //RPL_ENDOFNAMES
+            "366" => if let Some(chan) = message.args.get(1).and_then(|name| channels.get_mut(&channel::key(name))) {
+                chan.roster.end_names();
+            },
+            _ => (),
+        }
+    }
+
+    #[inline]
+    ///Returns whether bot is operator of channel.
+    fn has_op(&self, chan: &channel::Channel) -> bool {
+        chan.roster.is_op(&self.nick)
+    }
+
+    ///Handler to KICK.
+    fn kick(&self, message: Message, channels: &mut HashMap<String, channel::Channel>) {
+        if message.args.len() < 2 || message.args[1].to_lowercase() != self.nick.to_lowercase() {
+            return;
+        }
+
+        if let Some(chan) = channels.get_mut(&channel::key(&message.args[0])) {
+            let kicker = utils::get_nick(&message.prefix).unwrap_or("".to_string());
+            let reason = message.suffix.clone().unwrap_or("".to_string());
+            println!(">>>KICKED OUT OF {} by {}: {}", &chan.name, &kicker, &reason);
+
+            chan.kicked(&kicker, &reason);
+            self.notify_owners(&format!("I was kicked out of {} by {}: {}", &chan.name, &kicker, &reason));
+            self.schedule_join(chan);
+        }
+    }
+
+    ///Handler to failed JOIN.
+    ///
+    ///Bot retries until limit is reached, except for bad key as retry cannot help with it.
+    fn join_failed(&self, message: Message, channels: &mut HashMap<String, channel::Channel>) {
+        let chan = match message.args.get(1).and_then(|name| channels.get_mut(&channel::key(name))) {
+            Some(chan) => chan,
+            None       => return,
+        };
+        let reason = message.suffix.clone().unwrap_or("".to_string());
+        println!(">>>ERROR: Unable to join {}: {}", &chan.name, &reason);
+
+        //ERR_BADCHANNELKEY
+        if message.command != "475" && chan.rejoin_failed(self.rejoin_attempts) {
+            self.schedule_join(chan);
+        }
+        else {
+            self.notify_owners(&format!("I gave up on joining {}: {}", &chan.name, &reason));
+        }
+    }
+
+    ///Tries to take primary nickname back.
+    ///
+    ///GHOST disconnects whoever uses it, so nickname is changed right after it.
+    ///REGAIN changes nickname by itself.
+    fn regain_nick(&self) {
+        let primary = self.nicks.primary();
+
+        match (self.regain_method.as_ref(), self.config.nick_password.as_ref()) {
+            (Some(method), Some(password)) => {
+                self.send_service("NickServ", &format!("{} {} {}", method, primary, password));
+                if method == "REGAIN" {
+                    return;
+                }
+            },
+            _ => (),
+        }
+
+        self.send("", Message::new(None, "NICK", Some(vec![primary]), None), queue::Priority::High);
+    }
+
+    #[inline(always)]
+    ///Requests IRCv3 capabilities.
+    fn request_caps(&self) {
+        self.send("", Message::new(None, "CAP", Some(vec!["REQ"]), Some("account-tag")), queue::Priority::High);
+    }
+
+    ///Returns role of message's sender.
+    ///
+    ///Privileged roles are given only when sender's NickServ account is confirmed
+    ///either by IRCv3 account tag or by verification.
+    fn role(&self, message: &Message) -> acl::Role {
+        let prefix = match message.prefix {
+            Some(ref prefix) => prefix,
+            None             => return acl::Role::User,
+        };
+        let claimed = std::cmp::min(self.acl.role(prefix, None), acl::Role::User);
+        let nickname = utils::get_nick(&message.prefix).unwrap_or("".to_string());
+        let account = utils::get_tag(message, "account").or_else(|| self.identities.borrow().account(&nickname).map(|account| account.to_string()));
+
+        match account.map(|account| self.acl.verified_role(prefix, &account)) {
+            Some(role) if role > acl::Role::User => role,
+            _                                    => claimed,
         }
     }
 
-    ///Reconnects bot with delay.
-    fn reconnect(&mut self, delay_ms: u32) {
-        self.joined = false;
-        std::thread::sleep_ms(delay_ms);
-        self.server.reconnect().unwrap();
-        self.server.identify().unwrap();
+    #[inline]
+    ///Returns whether sender's identity is already known.
+    fn is_verified(&self, nickname: &str) -> bool {
+        self.account_tag || self.identities.borrow().is_known(nickname)
+    }
+
+    ///Postpones message until its sender's identity is verified.
+    fn verify(&self, nickname: &str, message: Message) {
+        if self.identities.borrow_mut().wait(nickname, message) {
+            match self.verify_method {
+                identity::Method::Whois  => self.send("", Message::new(None, "WHOIS", Some(vec![nickname]), None), queue::Priority::High),
+                identity::Method::Status => self.send_service("NickServ", &format!("STATUS {}", nickname)),
+                identity::Method::Acc    => self.send_service("NickServ", &format!("ACC {}", nickname)),
+            }
+        }
+    }
+
+    ///Handles messages which were waiting for sender's verification.
+    fn replay(&self, messages: Vec<Message>, channels: &mut HashMap<String, channel::Channel>) {
+        for message in messages {
+            if let Some(chan) = channels.get_mut(&channel::key(&message.args[0])) {
+                if let (Some(nickname), Some(usr_msg)) = (utils::get_nick(&message.prefix), message.suffix.clone()) {
+                    self.respond(message, &nickname, &usr_msg, chan);
+                }
+                continue;
+            }
+
+            self.private_query(message, channels);
+        }
+    }
+
+    ///Handler to replies on identity verification.
+    fn identity_reply(&self, message: Message, channels: &mut HashMap<String, channel::Channel>) {
+        let pending = match &message.command[..] {
+            //RPL_WHOISREGNICK
+            "307" if message.args.len() > 1 => self.identities.borrow_mut().resolve(&message.args[1], Some(message.args[1].clone())),
+            //RPL_WHOISACCOUNT
+            "330" if message.args.len() > 2 => self.identities.borrow_mut().resolve(&message.args[1], Some(message.args[2].clone())),
+            //RPL_ENDOFWHOIS
+            "318" if message.args.len() > 1 => self.identities.borrow_mut().finish(&message.args[1]),
+            "NOTICE" => match (utils::get_nick(&message.prefix), message.suffix.as_ref().and_then(|text| identity::parse_nickserv(text))) {
+                (Some(ref from), Some((ref nickname, identified))) if from.to_lowercase() == "nickserv" => {
+                    self.identities.borrow_mut().resolve(nickname, if identified { Some(nickname.clone()) } else { None })
+                },
+                _ => return,
+            },
+            _ => return,
+        };
+
+        self.replay(pending, channels);
+    }
+
+    ///Runs command if role is allowed to.
+    fn exec_command(&self, cmd: &commands::Command, nickname: &String, role: acl::Role, args: &[&str], chan: &mut channel::Channel) -> BotResponse {
+        let access = cmd.access_with(args);
+        if role < access {
+            if access > acl::Role::User && role != acl::Role::Ignored && !self.is_verified(nickname) {
+                return BotResponse::Unverified;
+            }
+            return cmd.denied();
+        }
+
+        cmd.exec(self, nickname, args, chan)
     }
 
     ///Handler to direct msgs i.e. to bot.
-    fn direct_response(&self, nickname: &String, usr_msg: &String, log: &mut log::IrcLog) -> BotResponse {
-        let usr_msg = usr_msg.to_lowercase();
+    ///
+    ///Command name is case insensitive, while its arguments are passed as is.
+    fn direct_response(&self, nickname: &String, role: acl::Role, usr_msg: &String, chan: &mut channel::Channel) -> BotResponse {
         let parts: Vec<&str> = usr_msg.split_whitespace().collect();
-        match parts[1] {
-            "ping" | "пинг"       => BotResponse::Channel("pong".to_string()),
-            "grep" | "find"       => KuuBot::command_grep(&parts),
-            "google"              => KuuBot::command_google(&parts),
-            "log"                 => self.command_log(nickname, &parts[2..], log),
-            "about" | "status"    => self.command_about(nickname, &log),
-            "help"                => KuuBot::command_help(),
-            "rape"                => KuuBot::command_rape(&parts[2..]),
-            "huiping" | "хуйпинг" => BotResponse::Channel("死になさいゴミムシ".to_string()),
-            "die"                 => KuuBot::command_die(nickname),
-            "sleep" |
-            "shutdown" | "bye"    => KuuBot::command_sleep(nickname),
-            _                     => BotResponse::Channel("...".to_string()),
+        match parts.get(1).and_then(|name| self.commands.find(&name.to_lowercase())) {
+            Some(cmd) => self.exec_command(cmd, nickname, role, &parts[2..], chan),
+            None      => BotResponse::Channel("...".to_string()),
         }
     }
 
     #[inline]
     ///Handler to all messages in general.
-    fn indirect_response(&self, nickname: &String, usr_msg: &String, log: &mut log::IrcLog) -> BotResponse {
+    fn indirect_response(&self, nickname: &String, role: acl::Role, usr_msg: &String, chan: &mut channel::Channel) -> BotResponse {
+        if usr_msg.starts_with("!") {
+            let parts: Vec<&str> = usr_msg[1..].split_whitespace().collect();
+            if let Some(cmd) = parts.first().and_then(|name| self.commands.find(&name.to_lowercase())) {
+                if cmd.trigger() == commands::Trigger::Any {
+                    return self.exec_command(cmd, nickname, role, &parts[1..], chan);
+                }
+            }
+        }
+
         let usr_msg = usr_msg.to_lowercase();
-        match &usr_msg[..] {
-            "!ping" | "!пинг"                 => BotResponse::Channel("pong".to_string()),
-            "!huiping" | "!хуйпинг"           => BotResponse::Channel("死になさいゴミムシ".to_string()),
-            _ if usr_msg.starts_with("!log")  => self.command_log(nickname, &usr_msg.split_whitespace().skip(1).collect::<Vec<&str>>(), log),
-            _ if usr_msg.contains("tadaima") ||
-                 usr_msg.contains("тадайма") ||
-                 usr_msg.contains("ただいま") => BotResponse::Channel("okaeri".to_string()),
-            _                                 => BotResponse::None,
+
+        if usr_msg.contains("tadaima") || usr_msg.contains("тадайма") || usr_msg.contains("ただいま") {
+            BotResponse::Channel("okaeri".to_string())
+        }
+        else {
+            BotResponse::None
+        }
+    }
+
+    #[inline(always)]
+    ///Puts message in outgoing queue.
+    ///
+    ///Messages are grouped by target, which is empty for messages to server itself.
+    fn send(&self, to: &str, message: Message, priority: queue::Priority) {
+        self.outbox.push(to, message, priority);
+    }
+
+    ///Returns how many bytes of text can be sent to target.
+    ///
+    ///Server relays it as `:<hostmask> <command> <target> :<text>\r\n` which must fit into 512 bytes.
+    fn text_budget(&self, command: &str, target: &str) -> usize {
+        let hostmask_len = if self.hostmask.is_empty() {
+            //Own hostmask is unknown yet so assume the longest one: nick!~user(10)@host(63)
+            std::cmp::max(self.nick.len(), self.server.config().nickname().len()) + 1 + 11 + 1 + 63
+        }
+        else {
+            self.hostmask.len()
+        };
+
+        512usize.saturating_sub(1 + hostmask_len + 1 + command.len() + 1 + target.len() + 2 + 2)
+    }
+
+    ///Puts private message in outgoing queue.
+    ///
+    ///Message is split into several if it doesn't fit into IRC line.
+    fn queue_msg(&self, to: &str, message: &str, priority: queue::Priority) {
+        for part in utils::split_message(message, self.text_budget("PRIVMSG", to), &self.continuation) {
+            self.send(to, Message::new(None, "PRIVMSG", Some(vec![to]), Some(&part)), priority);
+            if self.log_own {
+                self.outgoing.borrow_mut().push((to.to_string(), part));
+            }
+        }
+    }
+
+    ///Writes own outgoing messages into logs.
+    ///
+    ///Channel messages go to log of channel, while private ones to `private.log` if it is enabled.
+    fn log_outgoing(&self, channels: &mut HashMap<String, channel::Channel>) {
+        let outgoing: Vec<(String, String)> = self.outgoing.borrow_mut().drain(..).collect();
+
+        let hostmask = if self.hostmask.is_empty() { None } else { Some(self.hostmask.clone()) };
+
+        for (to, text) in outgoing {
+            if let Some(chan) = channels.get_mut(&channel::key(&to)) {
+                let mut entry = log::IrcEntry::new(self.nick.clone(), text);
+                entry.set_hostmask(hostmask.clone());
+                chan.log.add(entry);
+            }
+            else if self.log_own_private {
+                let mut private_log = self.private_log.borrow_mut();
+                if private_log.is_none() {
+                    let mut log = log::IrcLog::open(PRIVATE_LOG_PATH);
+                    log.set_max_size(self.log_max_size);
+                    *private_log = Some(log);
+                }
+
+                let mut entry = log::IrcEntry::new(self.nick.clone(), format!("-> {}: {}", to, text));
+                entry.set_hostmask(hostmask.clone());
+                private_log.as_mut().unwrap().add(entry);
+            }
         }
     }
 
+    #[inline(always)]
+    ///Sends command to services e.g. NickServ.
+    ///
+    ///It is never logged, as it may contain password.
+    fn send_service(&self, to: &str, message: &str) {
+        self.send(to, Message::new(None, "PRIVMSG", Some(vec![to]), Some(message)), queue::Priority::High);
+    }
+
     #[inline(always)]
     ///Sends private message.
     fn send_msg(&self, to: &str, message: &str) {
-        self.server.send_privmsg(to, message).unwrap();
+        self.queue_msg(to, message, queue::Priority::Normal);
     }
 
     #[inline(always)]
-    ///Sends bot's response
-    fn send_response(&self, response: BotResponse, nickname: &String) {
+    ///Sends bot's response.
+    ///
+    ///Channel response goes to `to` which is either channel or nickname for private queries.
+    ///Responses to owners take priority over others.
+    fn send_response(&self, response: BotResponse, to: &str, nickname: &String, role: acl::Role) {
+        let priority = if role == acl::Role::Owner { queue::Priority::High } else { queue::Priority::Normal };
+
         match response {
-            BotResponse::Channel(text) => self.send_msg(VNDIS, &format!("{}: {}", nickname, &text)),
+            BotResponse::Channel(text) => self.queue_msg(to, &format!("{}: {}", nickname, &text), priority),
             //for private response we allow to send several.
-            BotResponse::Private(text) => self.send_msg(&nickname, &text),
-            BotResponse::PrivateMulti(text) => for line in text.lines() { self.send_msg(&nickname, line); },
-            BotResponse::None => (),
+            BotResponse::Private(text) => self.queue_msg(&nickname, &text, priority),
+            BotResponse::PrivateMulti(text) => for line in text.lines() { self.queue_msg(&nickname, line, priority); },
+            BotResponse::None | BotResponse::Unverified => (),
             BotResponse::DyingMsg => {
-                self.send_msg(VNDIS, &format!("{}: Good bye, master", nickname));
-                self.server.send_quit("...").unwrap();
-                panic!("Shutting down by request of master");
+                self.queue_msg(to, &format!("{}: Good bye, master", nickname), priority);
+                self.stopping.set(true);
             }
         }
     }
 
     #[inline(always)]
     ///Returns bot's response
-    fn get_response(&self, nickname: &String, usr_msg: &String, log: &mut log::IrcLog) -> BotResponse {
-        if usr_msg.starts_with(&self.nick) {
-            self.direct_response(&nickname, &usr_msg, log)
+    fn get_response(&self, nickname: &String, role: acl::Role, usr_msg: &String, chan: &mut channel::Channel) -> BotResponse {
+        if role == acl::Role::Ignored {
+            BotResponse::None
+        }
+        else if usr_msg.starts_with(&self.nick) {
+            self.direct_response(&nickname, role, &usr_msg, chan)
         }
         else {
-            self.indirect_response(&nickname, &usr_msg, log)
+            self.indirect_response(&nickname, role, &usr_msg, chan)
         }
     }
 
     #[inline]
-    ///Handler to all VNDIS messages.
-    fn vndis_msg(&self, message: Message, log: &mut log::IrcLog) {
-        if let (Some(nickname), Some(usr_msg)) = (utils::get_nick(&message.prefix), message.suffix) {
-            let response = self.get_response(&nickname, &usr_msg, log);
+    ///Responds to channel message or postpones it until sender is verified.
+    fn respond(&self, message: Message, nickname: &String, usr_msg: &String, chan: &mut channel::Channel) {
+        let role = self.role(&message);
+        match self.get_response(nickname, role, usr_msg, chan) {
+            BotResponse::Unverified => self.verify(nickname, message),
+            response                => self.send_response(response, &chan.name, nickname, role),
+        }
+    }
+
+    ///Delivers memos which wait for nickname.
+    fn deliver_memos(&self, nickname: &str, chan: &str) {
+        let memos = self.memos.borrow_mut().take(nickname);
+        let now = time::get_time().sec;
+
+        for memo in memos {
+            let text = format!("{} told you {} ago on {}: {}", &memo.from, seen::format_duration(now - memo.time), &memo.channel, &memo.text);
+            if self.memo_private {
+                self.send_msg(nickname, &text);
+            }
+            else {
+                self.send_msg(chan, &format!("{}: {}", nickname, &text));
+            }
+        }
+    }
+
+    ///Sends reminders which are due and schedules wake up for the next one.
+    ///
+    ///Reminder goes to user in private if bot is not on its channel.
+    fn fire_reminders(&self, channels: &HashMap<String, channel::Channel>) {
+        let now = time::get_time().sec;
+        let due = self.scheduler.borrow_mut().due(now);
+        if due.is_empty() {
+            return;
+        }
+
+        for reminder in due {
+            //Reminders can be late after restart.
+            let late = if now - reminder.time > 60 { format!(" (sorry, I'm {} late)", seen::format_duration(now - reminder.time)) } else { "".to_string() };
+            if channels.get(&channel::key(&reminder.channel)).map_or(false, |chan| chan.joined) {
+                self.send_msg(&reminder.channel, &format!("{}: reminder: {}{}", &reminder.nick, &reminder.text, late));
+            }
+            else {
+                self.send_msg(&reminder.nick, &format!("reminder from {}: {}{}", &reminder.channel, &reminder.text, late));
+            }
+        }
+
+        self.schedule_wake();
+    }
 
-            self.send_response(response, &nickname);
+    #[inline]
+    ///Handler to all channel messages.
+    fn channel_msg(&self, message: Message, chan: &mut channel::Channel) {
+        if let (Some(nickname), Some(usr_msg)) = (utils::get_nick(&message.prefix), message.suffix.clone()) {
+            self.deliver_memos(&nickname, &chan.name);
+            //Commands are not expected within /me
+            let (kind, usr_msg) = match utils::parse_action(&usr_msg) {
+                Some(action) => (log::Kind::Action, action.to_string()),
+                None => {
+                    self.respond(message, &nickname, &usr_msg, chan);
+                    (log::Kind::Message, usr_msg.clone())
+                },
+            };
 
             let strip_mirc = regex::Regex::new(r"\x1f|\x02|\x01|\x12|\x0f|\x16|\x03(?:\d{1,2}(?:,\d{1,2})?)?").unwrap();
-            log.add(log::IrcEntry::new(nickname, strip_mirc.replace_all(&usr_msg, "")));
-            println!("{}", log.back().unwrap())
+            let text = strip_mirc.replace_all(&usr_msg, "");
+            self.seen.borrow_mut().update(&nickname, seen::Event::Message, &chan.name, Some(&text[..]), time::get_time().sec);
+            let mut entry = log::IrcEntry::with_kind(kind, nickname, text);
+            entry.set_hostmask(message.prefix.clone());
+            chan.log.add(entry);
+            println!("{}", chan.log.back().unwrap())
         }
         else {
-            println!(">>>ERROR: bad message over #vndis")
+            println!(">>>ERROR: bad message over {}", &chan.name)
         }
     }
 
     #[inline]
     ///Handler to private queries.
-    fn private_query(&self, message: Message, log: &log::IrcLog) {
+    fn private_query(&self, message: Message, channels: &HashMap<String, channel::Channel>) {
+        let role = self.role(&message);
         if let Some(nickname) = utils::get_nick(&message.prefix) {
-            if nickname.starts_with(MASTER) {
+            if role == acl::Role::Owner {
                 let usr_msg = message.suffix.unwrap().to_lowercase();
                 let mut parts = usr_msg.split_whitespace();
 
                 let response = match parts.next() {
-                    Some("status") | Some("about") => self.command_about(&nickname, log),
+                    Some("status") | Some("about") => BotResponse::PrivateMulti(channels.values().fold(format!("{}\n", &self),
+                                                                                                       |acc, chan| acc + &format!("{}\n", chan))),
+                    Some("migrate")                => BotResponse::PrivateMulti(KuuBot::migrate_logs(channels)),
+                    Some("help")                   => BotResponse::PrivateMulti("status | reload | migrate\n\
+                                                                                migrate imports log files of channels into database without duplicates. \
+                                                                                Set log_backend to sqlite and reload first, so that nothing is written to files afterwards".to_string()),
+                    Some("reload")                 => {
+                        *self.reload_requester.borrow_mut() = Some(nickname.clone());
+                        BotResponse::None
+                    },
                     None                           => BotResponse::Private("Umm...? What? You said nothing. Master, is everything ok?".to_string()),
                     _                              => BotResponse::Private("Did you mispell command? Try again :)".to_string()),
                 };
 
-                self.send_response(response, &nickname);
+                self.send_response(response, &nickname, &nickname, role);
+            }
+            else if role != acl::Role::Ignored && !self.is_verified(&nickname) {
+                self.verify(&nickname, message);
             }
-            else if !nickname.starts_with("py-ctcp") {
+            else if role != acl::Role::Ignored && !nickname.starts_with("py-ctcp") {
                 self.send_msg(&nickname, "Please do not bother me");
-                self.send_msg(VNDIS, &format!("{}: master, some weird {} is trying to abuse me :(", MASTER, &nickname));
+                if let Some(chan) = channels.values().find(|chan| chan.joined) {
+                    self.send_msg(&chan.name, &format!("{}: master, some weird {} is trying to abuse me :(", MASTER, &nickname));
+                }
             }
         }
         else {
@@ -178,89 +915,287 @@ impl KuuBot {
         }
     }
 
+    ///Imports log files of channels into database.
+    fn migrate_logs(channels: &HashMap<String, channel::Channel>) -> String {
+        channels.values().fold(String::new(), |acc, chan| {
+            let name = &chan.name;
+            let path = channel::Channel::log_file(name);
+            if std::fs::metadata(&path).is_err() {
+                return acc + &format!("{}: there is no {}\n", name, &path);
+            }
+            let result = match sqlite::SqliteStore::open(sqlite::DB_PATH, name) {
+                Ok(mut store) => {
+                    let entries = log::FileStore::open(&path).range(&log::FilterLog::None);
+                    format!("{}: {} entries of {} are imported into {}", name, store.import(entries), &path, sqlite::DB_PATH)
+                },
+                Err(err) => format!("{}: {}", name, err),
+            };
+            acc + &result + "\n"
+        })
+    }
+
     #[inline]
     ///Message dispatcher.
-    fn handle_msg(&self, message: Message, log: &mut log::IrcLog) {
-        if !self.joined { return; }
+    fn handle_msg(&self, message: Message, channels: &mut HashMap<String, channel::Channel>) {
+        if let Some(chan) = channels.get_mut(&channel::key(&message.args[0])) {
+            if chan.joined {
+                self.channel_msg(message, chan);
+            }
+            return;
+        }
 
-        match &*message.args[0] {
-            VNDIS => self.vndis_msg(message, log),
-            //Most possibly private query.
-            _ if message.args[0].starts_with(&self.nick) => self.private_query(message, log),
-            _ => println!(">>>ERROR: Got unexpected message={:?}", message),
+        //Most possibly private query.
+        if !self.nick.is_empty() && message.args[0].starts_with(&self.nick) {
+            self.private_query(message, channels);
+        }
+        else {
+            println!(">>>ERROR: Got unexpected message={:?}", message);
         }
     }
 
     #[inline(always)]
-    ///Welcome joined persons on VNDIS.
-    fn welcome_vndis(&self, nickname: &String) -> BotResponse {
-        match nickname {
-            _ if nickname.starts_with(MASTER) => BotResponse::Channel("Welcome, dear master!".to_string()),
-            _ => BotResponse::None,
+    ///Welcome joined persons.
+    fn welcome_user(&self, role: acl::Role) -> BotResponse {
+        match role {
+            acl::Role::Owner => BotResponse::Channel("Welcome, dear master!".to_string()),
+            _                => BotResponse::None,
         }
     }
 
-    #[inline(always)]
-    ///Welcome joined persons.
-    fn welcome(&self, message: Message) {
-        if let (Some(nickname), Some(usr_msg)) = (utils::get_nick(&message.prefix), message.suffix) {
-            let response = match &usr_msg[..] {
-                VNDIS => self.welcome_vndis(&nickname),
-                _     => BotResponse::None,
+    ///Welcome joined persons on channel.
+    ///
+    ///Greeting which matches person's hostmask takes priority over default one.
+    ///Anyone can take owner's nickname, so owner is welcomed only if account is known.
+    fn welcome(&self, message: Message, chan: &channel::Channel) {
+        if let (Some(nickname), Some(prefix)) = (utils::get_nick(&message.prefix), message.prefix.as_ref()) {
+            let mut greetings = self.greetings.borrow_mut();
+            if !greetings.should_greet(&nickname, &chan.name, time::get_time().sec) {
+                return;
+            }
+
+            let role = self.role(&message);
+            let response = match greetings.find(prefix, &chan.name) {
+                Some(greeting) if role != acl::Role::Ignored => BotResponse::Channel(greeting.text.clone()),
+                _                                            => self.welcome_user(role),
             };
 
-            self.send_response(response, &nickname);
+            self.send_response(response, &chan.name, &nickname, role);
+        }
+    }
+
+    ///Handler for command greet.
+    ///
+    ///Greeting is matched on user's `*!user@host`, if it is known from channel's roster.
+    fn command_greet(&self, nickname: &String, args: &[&str], chan: &channel::Channel) -> BotResponse {
+        let mask = match chan.roster.get(nickname).and_then(|member| member.hostmask.as_ref()).and_then(|hostmask| hostmask.find('!').map(|pos| &hostmask[pos..])) {
+            Some(userhost) => format!("*{}", userhost),
+            None           => format!("{}!*@*", nickname),
+        };
+
+        match args.first().map(|arg| arg.to_lowercase()).as_ref().map(|arg| &arg[..]) {
+            None => match self.greetings.borrow().find(&mask.replace('*', nickname), &chan.name) {
+                Some(greeting) => BotResponse::Channel(format!("your greeting is: {}", &greeting.text)),
+                None           => BotResponse::Channel("you have no greeting. Set it with greet <text>".to_string()),
+            },
+            Some("off") => if self.greetings.borrow_mut().remove(&mask, &chan.name) {
+                BotResponse::Channel("I won't greet you anymore".to_string())
+            }
+            else {
+                BotResponse::Channel("you have no greeting".to_string())
+            },
+            Some(_) => {
+                self.greetings.borrow_mut().set(&mask, &chan.name, &args.join(" "));
+                BotResponse::Channel(format!("I'll greet you on {} from now on", &chan.name))
+            },
         }
     }
 
     ///Starts bot which continuously handles messages.
-    fn run(&mut self) {
-        let mut log = log::IrcLog::new();
-        self.server.identify().unwrap();
-        loop {
+    ///
+    ///Returns exit code of process which is non-zero if bot gives up on reconnect.
+    fn run(&mut self) -> i32 {
+        let mut channels = self.server.config().channels().iter()
+                                                          .map(|name| (channel::key(name), channel::Channel::with_backend(name, self.log_backend)))
+                                                          .collect::<HashMap<String, channel::Channel>>();
+        for chan in channels.values_mut() {
+            chan.log.set_max_size(self.log_max_size);
+        }
+        if self.seen.borrow().is_empty() {
+            let mut seen = self.seen.borrow_mut();
+            for chan in channels.values_mut() {
+                seen.import(&chan.name, &mut chan.log);
+            }
+            seen.save();
+        }
+        let server = self.server.clone();
+        let outbox = self.outbox.clone();
+        let signal_outbox = self.outbox.clone();
+        signal::spawn_blocked(|| {
+            self.outbox.start(move |message| if let Err(err) = server.send(message) {
+                println!(">>>ERROR: Unable to send message: {}", err);
+            });
+            //Server replies with PONG, so that main loop can fire reminders and rejoin channels.
+            self.waker.start(move || outbox.push("", Message::new(None, "PING", None, Some("scheduler")), queue::Priority::High));
+            //Same for stop and reload requested by signals.
+            signal::watch(move || signal_outbox.push("", Message::new(None, "PING", None, Some("signal")), queue::Priority::High));
+        });
+        self.schedule_wake();
+        if let Err(err) = self.server.identify() {
+            println!(">>>ERROR: Unable to identify: {}", err);
+            self.backoff.failure(format!("{}", err));
+        }
+        self.request_caps();
+        'main: loop {
+            let mut is_reload = false;
             for message in self.server.iter() {
                 match message {
                     Ok(message) => {
+                        self.log_event(&message, &mut channels);
+                        self.track_members(&message, &mut channels);
+
                         match &message.command[..] {
-                            "PRIVMSG" => self.handle_msg(message, &mut log),
-                            "JOIN"    => if !self.joined {
-                                self.joined = message.suffix.unwrap_or("".to_string()) == VNDIS;
-                                if self.joined {
-                                    self.nick = utils::get_nick(&message.prefix).unwrap_or_else(|| panic!("Unable to confirm own nick!?"));
-                                    println!(">>>Joined {}", VNDIS);
+                            "PRIVMSG" => self.handle_msg(message, &mut channels),
+                            "JOIN"    => {
+                                let name = message.args.get(0).or(message.suffix.as_ref()).cloned().unwrap_or("".to_string());
+                                if let Some(chan) = channels.get_mut(&channel::key(&name)) {
+                                    if !chan.joined {
+                                        chan.join();
+                                        self.nick = utils::get_nick(&message.prefix).unwrap_or_else(|| panic!("Unable to confirm own nick!?"));
+                                        self.hostmask = message.prefix.clone().unwrap_or("".to_string());
+                                        println!(">>>Joined {}", &chan.name);
+                                    }
+                                    else {
+                                        if let Some(nickname) = utils::get_nick(&message.prefix) {
+                                            self.deliver_memos(&nickname, &chan.name);
+                                        }
+                                        self.welcome(message, chan);
+                                    }
+                                }
+                            },
+                            "KICK"   => self.kick(message, &mut channels),
+                            //ERR_CHANNELISFULL, ERR_INVITEONLYCHAN, ERR_BANNEDFROMCHAN, ERR_BADCHANNELKEY
+                            "471" | "473" | "474" | "475" => self.join_failed(message, &mut channels),
+                            "NICK" | "QUIT" => if let Some(nickname) = utils::get_nick(&message.prefix) {
+                                let mut identities = self.identities.borrow_mut();
+                                identities.forget(&nickname);
+                                if message.command == "NICK" {
+                                    let new_nick = message.suffix.clone().or_else(|| message.args.get(0).cloned()).unwrap_or("".to_string());
+                                    identities.forget(&new_nick);
+
+                                    if !new_nick.is_empty() && nickname.to_lowercase() == self.nick.to_lowercase() {
+                                        println!(">>>Nick is changed to {}", &new_nick);
+                                        if let Some(pos) = self.hostmask.find('!') {
+                                            self.hostmask = format!("{}{}", &new_nick, &self.hostmask[pos..]);
+                                        }
+                                        self.nick = new_nick;
+                                    }
                                 }
-                            }
-                            else {
-                                self.welcome(message);
                             },
-                            "KICK"   => {
-                                println!(">>>KICKED OUT OF {}", &*message.args[0]);
-                                match &*message.args[0] {
-                                    VNDIS => {
-                                        self.joined = false;
-                                        self.server.send_join(VNDIS).unwrap();
-                                    },
-                                    _     => (),
+                            //ERR_NICKNAMEINUSE, ERR_NICKCOLLISION
+                            //Nickname is `*` only while bot is not registered yet, otherwise it is failed regain.
+                            "433" | "436" => if message.args.get(0).map_or(false, |nick| nick == "*") {
+                                let alt = self.nicks.next_alt();
+                                println!(">>>Nick {} is in use. Try {}", message.args.get(1).map_or("", |nick| &nick[..]), &alt);
+                                self.send("", Message::new(None, "NICK", Some(vec![&alt[..]]), None), queue::Priority::High);
+                            },
+                            "CAP" => if message.args.get(1).map_or(false, |sub| sub == "ACK") &&
+                                        message.suffix.as_ref().map_or(false, |caps| caps.split_whitespace().any(|cap| cap == "account-tag")) {
+                                self.account_tag = true;
+                            },
+                            "307" | "318" | "330" | "NOTICE" => self.identity_reply(message, &mut channels),
+                            //RPL_WELCOME
+                            "001" => {
+                                self.backoff.registered();
+                                self.nicks.reset();
+                                if let Some(nick) = message.args.get(0) {
+                                    self.nick = nick.clone();
                                 }
                             },
+                            //K-line, throttling and alike are reported by ERROR or ERR_YOUREBANNEDCREEP right before disconnect.
+                            "ERROR" | "465" => {
+                                let reason = message.suffix.clone().unwrap_or("".to_string());
+                                println!(">>>SERVER ERROR: {}", &reason);
+                                self.backoff.failure(reason);
+                            },
                             _        => (),
                         }
                     },
 
                     Err(err) => println!(">>>ERROR: {}", err),
                 }
+
+                if signal::is_stop() {
+                    self.stopping.set(true);
+                }
+
+                if self.stopping.get() {
+                    break 'main;
+                }
+
+                self.fire_reminders(&channels);
+                self.rejoin_channels(&mut channels);
+                self.log_outgoing(&mut channels);
+
+                if self.nicks.should_regain(&self.nick, time::get_time().sec) {
+                    self.regain_nick();
+                }
+
+                if signal::is_reload() || self.reload_requester.borrow().is_some() {
+                    is_reload = true;
+                    break;
+                }
+            }
+
+            if is_reload {
+                self.reload_report(&mut channels);
+                continue;
             }
+
             println!(">>>ERROR: Connection loss");
-            self.reconnect(10);
+            for chan in channels.values_mut() {
+                chan.joined = false;
+            }
+            if !self.reconnect(&mut channels) {
+                break;
+            }
             std::io::stdout().flush().unwrap();
         }
+
+        self.shutdown(&mut channels);
+        if self.backoff.is_exhausted() { 1 } else { 0 }
+    }
+
+    ///Gracefully stops bot.
+    ///
+    ///Sends remaining messages and QUIT, then flushes all logs.
+    fn shutdown(&self, channels: &mut HashMap<String, channel::Channel>) {
+        const FLUSH_TIMEOUT_MS: u64 = 10000;
+
+        if !self.outbox.flush(FLUSH_TIMEOUT_MS) {
+            println!(">>>ERROR: Not all messages are sent before quit. Left={}", self.outbox.len());
+        }
+        self.send("", Message::new(None, "QUIT", None, Some(&self.quit_message)), queue::Priority::High);
+        self.outbox.flush(FLUSH_TIMEOUT_MS);
+
+        self.log_outgoing(channels);
+        for chan in channels.values_mut() {
+            chan.joined = false;
+            chan.log.flush();
+        }
+        if let Some(ref mut private_log) = *self.private_log.borrow_mut() {
+            private_log.flush();
+        }
+        self.seen.borrow_mut().save();
+
+        println!(">>>Shutting down");
+        std::io::stdout().flush().unwrap();
     }
 
     ///Upload log dump to gist.
-    fn upload(&self, log: &mut log::IrcLog, nickname: &String, filter: &log::FilterLog) {
-        let paste = log.get_all(filter);
+    fn upload(&self, chan: &mut channel::Channel, nickname: &String, filter: &(log::FilterLog, log::KindFilter)) {
+        let paste = chan.log.get_all(filter);
         if paste.is_empty() {
-            self.send_msg(VNDIS, &format!("{}: I'm sorry there are no logs for your request :(", nickname));
+            self.send_msg(&chan.name, &format!("{}: I'm sorry there are no logs for your request :(", nickname));
             return;
         }
 
@@ -272,14 +1207,14 @@ impl KuuBot {
         let client = hyper::Client::new();
 
         let paste = format!(r##"{{
-                                "description": "#vndis_log",
+                                "description": "{}_log",
                                 "files": {{
                                     "vndis_log": {{
                                         "content": "{}"
                                         }}
                                     }}
                                 }}
-                            "##, utils::Escape(paste));
+                            "##, &chan.name, utils::Escape(paste));
 
         let mut res = client.request(hyper::method::Method::Patch, "https://api.github.com/gists/9f58fe727c0cea299c46")
                             .headers(headers)
@@ -293,10 +1228,10 @@ impl KuuBot {
             let pos = pos + 10;
             let link = &link[pos..];
             let end = link.find("\"").unwrap();
-            self.send_msg(VNDIS, &format!("{}: log dump: {} | len={} | Filter={}", nickname, &link[..end], log_size, filter));
+            self.send_msg(&chan.name, &format!("{}: log dump: {} | len={} | Filter={} | Kinds={}", nickname, &link[..end], log_size, filter.0, filter.1));
         }
         else {
-            self.send_msg(VNDIS, &format!("{}: i failed to upload logs :( Check up reason in my console.", nickname));
+            self.send_msg(&chan.name, &format!("{}: i failed to upload logs :( Check up reason in my console.", nickname));
             println!(">>>ERROR: bad github gist result:{}", &link);
         }
     }
@@ -321,117 +1256,227 @@ impl KuuBot {
 
     #[inline(always)]
     ///Handler for command help.
-    fn command_help() -> BotResponse {
-        BotResponse::PrivateMulti(USAGE.to_string())
+    fn command_help(&self) -> BotResponse {
+        BotResponse::PrivateMulti(self.commands.usage())
     }
 
-    #[inline(always)]
-    ///Gentle handler for command exit.
-    ///
-    ///This command will panic bot later on for graceful shutdown.
-    fn command_sleep(nickname: &String) -> BotResponse {
-        if nickname.starts_with(MASTER) {
-            BotResponse::DyingMsg
+    #[inline]
+    ///Handler for command about.
+    fn command_about(&self, chan: &channel::Channel) -> BotResponse {
+        BotResponse::Private(format!("{} {} op={}", &self, chan, self.has_op(chan)))
+    }
+
+    ///Handler for command seen.
+    fn command_seen(&self, nickname: &String, args: &[&str], chan: &channel::Channel) -> BotResponse {
+        let nick = match args.first() {
+            Some(nick) => nick,
+            None       => return BotResponse::Channel("whom should I look for?".to_string()),
+        };
+
+        if nick.to_lowercase() == nickname.to_lowercase() {
+            return BotResponse::Channel("you are right here, silly".to_string());
         }
-        else {
-            BotResponse::Channel("It is for my master only".to_string())
+        else if nick.to_lowercase() == self.nick.to_lowercase() {
+            return BotResponse::Channel("I'm right here".to_string());
+        }
+
+        let is_here = chan.roster.get(nick).is_some();
+        match (self.seen.borrow().get(nick), is_here) {
+            (Some(seen), true)  => BotResponse::Channel(format!("{} is here. {}", &seen.nick, seen.describe(time::get_time().sec))),
+            (Some(seen), false) => BotResponse::Channel(seen.describe(time::get_time().sec)),
+            (None, true)        => BotResponse::Channel(format!("{} is here, but I have not seen anything from them yet", nick)),
+            (None, false)       => BotResponse::Channel(format!("I have never seen {}", nick)),
         }
     }
 
-    #[inline(always)]
-    ///Handler for command exit.
-    ///
-    ///This command will panic bot later on for graceful shutdown.
-    fn command_die(nickname: &String) -> BotResponse {
-        if nickname.starts_with(MASTER) {
-            BotResponse::DyingMsg
+    ///Handler for command tell.
+    fn command_tell(&self, nickname: &String, args: &[&str], chan: &channel::Channel) -> BotResponse {
+        if args.len() < 2 {
+            return BotResponse::Channel("tell whom and what?".to_string());
         }
-        else {
-            BotResponse::Channel("死になさいゴミムシ".to_string())
+
+        let to = args[0];
+        if to.to_lowercase() == self.nick.to_lowercase() {
+            return BotResponse::Channel("I'm listening right now".to_string());
+        }
+        else if to.to_lowercase() == nickname.to_lowercase() {
+            return BotResponse::Channel("you can tell it yourself".to_string());
+        }
+
+        match self.memos.borrow_mut().add(nickname, to, &chan.name, &args[1..].join(" "), time::get_time().sec) {
+            Ok(id)     => BotResponse::Channel(format!("I'll tell {} when they are around. Memo #{}", to, id)),
+            Err(error) => BotResponse::Channel(error),
         }
     }
 
-    #[inline]
-    ///Handler for command about.
-    ///
-    ///Response only to master.
-    fn command_about(&self, nickname: &String, log: &log::IrcLog) -> BotResponse {
-        if nickname.starts_with(MASTER) {
-            BotResponse::Private(format!("{} {}", &self, log))
+    ///Handler for command memo.
+    fn command_memo(&self, nickname: &String, args: &[&str]) -> BotResponse {
+        match args.first().map(|arg| arg.to_lowercase()).as_ref().map(|arg| &arg[..]) {
+            None | Some("list") => {
+                let memos = self.memos.borrow();
+                let memos = memos.list(nickname);
+                if memos.is_empty() {
+                    return BotResponse::Private("You have no pending memos".to_string());
+                }
+
+                let now = time::get_time().sec;
+                BotResponse::PrivateMulti(memos.iter().fold(format!("You have {} pending memos\n", memos.len()), |acc, memo| {
+                    acc + &format!("#{} to {} {} ago: {}\n", memo.id, &memo.to, seen::format_duration(now - memo.time), &memo.text)
+                }))
+            },
+            Some("cancel") => match args.get(1).and_then(|id| id.trim_left_matches('#').parse::<u64>().ok()) {
+                Some(id) if self.memos.borrow_mut().cancel(nickname, id) => BotResponse::Channel(format!("memo #{} is cancelled", id)),
+                Some(id)                                                 => BotResponse::Channel(format!("you have no memo #{}", id)),
+                None                                                     => BotResponse::Channel("which memo should I cancel?".to_string()),
+            },
+            Some(_) => BotResponse::Channel("memo [list] | cancel <id>".to_string()),
         }
-        else {
-            BotResponse::Channel("It is for my master only".to_string())
+    }
+
+    ///Handler for command who/users.
+    fn command_who(args: &[&str], chan: &channel::Channel) -> BotResponse {
+        if let Some(nick) = args.first() {
+            return match chan.roster.get(nick) {
+                Some(member) => BotResponse::Private(format!("{}{} | {} | joined {}",
+                                                             member.prefix(), &member.nick,
+                                                             member.hostmask.as_ref().map_or("hostmask is unknown", |hostmask| &hostmask[..]),
+                                                             member.joined.map_or("before me".to_string(), |joined| {
+                                                                 time::at(time::Timespec::new(joined, 0)).strftime(log::TIME_FORMAT).unwrap().to_string()
+                                                             }))),
+                None         => BotResponse::Channel(format!("there is no {} on {}", nick, &chan.name)),
+            };
         }
+
+        let members = chan.roster.members().iter().map(|member| format!("{}{}", member.prefix(), &member.nick)).collect::<Vec<_>>();
+        BotResponse::Private(format!("{} users on {}: {}", members.len(), &chan.name, members.join(" ")))
     }
 
     #[inline]
     ///Handler for command google.
-    fn command_google(parts: &[&str]) -> BotResponse {
-        if parts.len() < 3 {
+    fn command_google(args: &[&str]) -> BotResponse {
+        if args.is_empty() {
             return BotResponse::Channel("google nothing...?".to_string());
         }
 
-        BotResponse::Channel(format!("http://lmgtfy.com/?q={}", parts[2..].join("+")))
+        BotResponse::Channel(format!("http://lmgtfy.com/?q={}", args.join("+")))
     }
 
     #[inline]
     ///Handler for command grep/find.
-    fn command_grep(parts: &[&str]) -> BotResponse {
-        if parts.len() < 4 {
+    fn command_grep(args: &[&str]) -> BotResponse {
+        if args.len() < 2 {
             return BotResponse::Channel("what? Nothing...?".to_string());
         }
 
-        match parts[2] {
-            "vn"    => BotResponse::Channel(format!("vndb: https://vndb.org/v/all?q={};fil=tagspoil-0;o=d;s=rel", parts[3..].join("+"))),
+        match &args[0].to_lowercase()[..] {
+            "vn"    => BotResponse::Channel(format!("vndb: https://vndb.org/v/all?q={};fil=tagspoil-0;o=d;s=rel", args[1..].join("+"))),
             bad @ _ => BotResponse::Channel(format!("i do not know what is >{}<", bad)),
         }
     }
 
     #[inline(always)]
-    fn command_rape(parts: &[&str]) -> BotResponse {
-        match parts.iter().next() {
+    fn command_rape(args: &[&str]) -> BotResponse {
+        match args.iter().next() {
             Some(&MASTER) => BotResponse::Channel("umm... no...".to_string()),
             None | _      => BotResponse::Channel("へんたい。。。".to_string()),
         }
     }
 
-    ///Parses string "num<d/m/h" to time.
-    fn parse_filter_time(filter_str: &str) -> Result<time::Tm, BotResponse> {
-        const TYPES: &'static[char] = &['m', 'h', 'd'];
-        let mut filter_chars = filter_str.chars();
-        let filter_type = filter_chars.next_back().unwrap();
-        let filter_val = filter_chars.collect::<String>().parse::<i64>();
+    ///Parses string like "num<m/h/d>" or "2h30m" to time in the past as seconds since epoch.
+    fn parse_filter_time(filter_str: &str) -> Result<i64, BotResponse> {
+        //Ten years should be enough for everyone.
+        const MAX_SECS: i64 = 315360000;
 
-        if filter_val.is_err() || !TYPES.contains(&filter_type) {
-            return Err(BotResponse::Channel(format!(">{}< is not normal filter. It should be num<m/h/d>", filter_str)));
-        }
-
-        //@TODO: handle 0 value as bad one?
-        let filter_val = filter_val.unwrap();
-        if filter_val < 0 {
+        if filter_str.starts_with('-') {
             return Err(BotResponse::Channel("filter cannot be negative...".to_string()));
         }
 
-        let time_before = time::now() - match filter_type {
-            'm' => time::Duration::minutes(filter_val),
-            'h' => time::Duration::hours(filter_val),
-            _ => time::Duration::days(filter_val),
+        let secs = match utils::parse_duration(filter_str) {
+            Some(secs) if secs <= MAX_SECS => secs,
+            _ => return Err(BotResponse::Channel(format!(">{}< is not normal filter. It should be num<m/h/d> e.g. 2h30m", filter_str))),
         };
-        //See implementation of Sub<Duration>.
-        //It seems that result will be in UTC isntead of original timezone.
-        //For now just manually convert it.
-        Ok(time_before.to_local())
+
+        Ok(time::get_time().sec - secs)
     }
 
+    ///Handler for command remind.
+    fn command_remind(&self, nickname: &String, args: &[&str], chan: &channel::Channel) -> BotResponse {
+        //A year ahead is far enough.
+        const MAX_SECS: i64 = 31536000;
+        const USAGE: &'static str = "remind [me] in <2h30m> | at [YYYY-MM-DD] <HH:MM> [to] <text> | list | delete <id>";
+
+        let args = if args.first().map_or(false, |arg| arg.to_lowercase() == "me") { &args[1..] } else { args };
+        let now = time::get_time().sec;
+
+        let when = match args.first().map(|arg| arg.to_lowercase()).as_ref().map(|arg| &arg[..]) {
+            Some("list")   => return self.reminder_list(nickname, now),
+            Some("delete") => {
+                let id = args.get(1).and_then(|id| id.trim_left_matches('#').parse::<u64>().ok());
+                let is_deleted = id.map_or(false, |id| self.scheduler.borrow_mut().delete(nickname, id));
+
+                return match id {
+                    Some(id) if is_deleted => {
+                        self.schedule_wake();
+                        BotResponse::Channel(format!("reminder #{} is deleted", id))
+                    },
+                    Some(id) => BotResponse::Channel(format!("you have no reminder #{}", id)),
+                    None     => BotResponse::Channel("which reminder should I delete?".to_string()),
+                };
+            },
+            Some("in") => args.get(1).and_then(|duration| utils::parse_duration(&duration.to_lowercase()))
+                                     .map(|secs| (now + secs, &args[2..])),
+            Some("at") => match (args.get(1), args.get(2)) {
+                (Some(date), Some(clock)) if date.contains('-') => scheduler::parse_at(Some(date), clock, now).map(|time| (time, &args[3..])),
+                (Some(clock), _)                                => scheduler::parse_at(None, clock, now).map(|time| (time, &args[2..])),
+                _                                               => None,
+            },
+            _ => return BotResponse::Private(USAGE.to_string()),
+        };
+
+        let (time, text) = match when {
+            Some((time, text)) if time > now && time - now <= MAX_SECS => (time, text),
+            Some(_) => return BotResponse::Channel("I can remind only within a year from now".to_string()),
+            None    => return BotResponse::Channel("I don't understand when. Try e.g. in 2h30m or at 18:30".to_string()),
+        };
+        let text = if text.first().map_or(false, |word| word.to_lowercase() == "to") { &text[1..] } else { text };
+        if text.is_empty() {
+            return BotResponse::Channel("remind you of what?".to_string());
+        }
+
+        let result = self.scheduler.borrow_mut().add(nickname, &chan.name, time, &text.join(" "));
+        match result {
+            Ok(id) => {
+                self.schedule_wake();
+                BotResponse::Channel(format!("I'll remind you in {}. Reminder #{}", seen::format_duration(time - now), id))
+            },
+            Err(error) => BotResponse::Channel(error),
+        }
+    }
+
+    ///Handler for remind sub-command list.
+    fn reminder_list(&self, nickname: &String, now: i64) -> BotResponse {
+        let scheduler = self.scheduler.borrow();
+        let reminders = scheduler.list(nickname);
+        if reminders.is_empty() {
+            return BotResponse::Private("You have no pending reminders".to_string());
+        }
+
+        BotResponse::PrivateMulti(reminders.iter().fold(format!("You have {} pending reminders\n", reminders.len()), |acc, reminder| {
+            acc + &format!("#{} in {} on {}: {}\n", reminder.id, seen::format_duration(reminder.time - now), &reminder.channel, &reminder.text)
+        }))
+    }
 
     ///Handler for command log.
-    fn command_log(&self, nickname: &String, parts: &[&str], log: &mut log::IrcLog) -> BotResponse {
+    fn command_log(&self, nickname: &String, parts: &[&str], chan: &mut channel::Channel) -> BotResponse {
         let mut parts = parts.iter();
-        match parts.next() {
-            Some(&"last") => KuuBot::command_log_last(log, &mut parts),
-            Some(&"dump") => self.command_log_dump(nickname, log, &mut parts),
-            Some(&"len")  => BotResponse::Private(format!("Log size is {}", log.len())),
-            Some(&"help") => BotResponse::Private("log <last> [num] | <len> | <dump> [last num<m/h/d>]".to_string()),
+        match parts.next().map(|part| part.to_lowercase()).as_ref().map(|part| &part[..]) {
+            Some("last") => KuuBot::command_log_last(&mut chan.log, &mut parts),
+            Some("dump") => self.command_log_dump(nickname, chan, &mut parts),
+            Some("search") => KuuBot::command_log_search(&mut chan.log, parts.as_slice()),
+            Some("len")  => BotResponse::Private(format!("Log size is {}, total {}", chan.log.len(), chan.log.count(&log::FilterLog::None))),
+            Some("help") => BotResponse::Private("log <last> [num] | <len> | <search> <words> | <dump> [last num<m/h/d>] [+kind|-kind...]. \
+                                                 Search matches words anywhere in messages, but only beginnings of words if log is in database. \
+                                                 Dump is for trusted only".to_string()),
             None => BotResponse::Channel("Um... what do you want? Do you need help?".to_string()),
             _ => BotResponse::Channel("I don't know such log command...".to_string()),
         }
@@ -452,7 +1497,7 @@ impl KuuBot {
         if num > 0 {
             let num = num as usize;
             let first = format!("Last {} messages\n", num);
-            BotResponse::PrivateMulti(log.iter().rev().take(num).collect::<Vec<_>>().into_iter().rev().fold(first, |acc, item| acc + &format!("{}\n", item)))
+            BotResponse::PrivateMulti(log.last(num).iter().fold(first, |acc, item| acc + &format!("{}\n", item)))
         }
         else {
             let num = num.abs() as usize;
@@ -461,28 +1506,47 @@ impl KuuBot {
         }
     }
 
+    ///Handler for log sub-command search.
+    fn command_log_search(log: &mut log::IrcLog, args: &[&str]) -> BotResponse {
+        if args.is_empty() {
+            return BotResponse::Channel("what should I look for?".to_string());
+        }
+
+        let text = args.join(" ");
+        let found = log.search(&text, LOG_SEARCH_LIMIT);
+        if found.is_empty() {
+            BotResponse::Private(format!("Nothing is found for: {}", &text))
+        }
+        else {
+            let first = format!("Found {} messages\n", found.len());
+            BotResponse::PrivateMulti(found.iter().fold(first, |acc, item| acc + &format!("{}\n", item)))
+        }
+    }
+
     ///Handler for log sub-command dump.
-    fn command_log_dump(&self, nickname: &String, log: &mut log::IrcLog, parts: &mut std::slice::Iter<&str>) -> BotResponse {
-        let filter: log::FilterLog;
-        match parts.next() {
-            Some(&"last") => {
-                if let Some(filter_str) = parts.next() {
-                    match KuuBot::parse_filter_time(filter_str) {
-                        Ok(time)      => filter = log::FilterLog::Last(time),
-                        Err(response) => return response,
-                    }
-                }
-                else {
-                    return BotResponse::Channel("you forgot to tell me filter value".to_string());
+    fn command_log_dump(&self, nickname: &String, chan: &mut channel::Channel, parts: &mut std::slice::Iter<&str>) -> BotResponse {
+        let mut time_filter = log::FilterLog::None;
+        let mut rest = parts.as_slice();
+
+        if rest.first().map_or(false, |part| part.to_lowercase() == "last") {
+            if let Some(filter_str) = rest.get(1) {
+                match KuuBot::parse_filter_time(filter_str) {
+                    Ok(time)      => time_filter = log::FilterLog::Last(time),
+                    Err(response) => return response,
                 }
-            },
-            None => filter = log::FilterLog::None,
-            filter @ _ => {
-                return BotResponse::Channel(format!("there is no such filter >{}<", filter.unwrap()));
-            },
+            }
+            else {
+                return BotResponse::Channel("you forgot to tell me filter value".to_string());
+            }
+            rest = &rest[2..];
         }
 
-        self.upload(log, nickname, &filter);
+        let filter = match log::KindFilter::parse(rest) {
+            Ok(kinds) => (time_filter, kinds),
+            Err(error) => return BotResponse::Channel(error),
+        };
+
+        self.upload(chan, nickname, &filter);
         BotResponse::None
     }
 }
@@ -492,8 +1556,11 @@ fn main() {
     std::env::set_current_dir(std::env::current_exe().unwrap().parent().unwrap())
               .unwrap_or_else(|err| panic!("cannot enter my own directory :(. Err={}", err));
 
+    signal::init();
+
     let mut bot = KuuBot::new();
-    bot.run();
+    let code = bot.run();
+    std::process::exit(code);
 }
 
 #[cfg(test)]
@@ -510,25 +1577,34 @@ mod tests {
         file.write_all(CONFIG.as_bytes()).unwrap_or(())
     }
 
+    ///Creates bot on `#vndis` and nickname of user who talks to it.
+    fn bot_on_channel() -> (super::KuuBot, super::channel::Channel, String) {
+        pre_condition();
+
+        (super::KuuBot::new(), super::channel::Channel::new("#vndis"), "Someone".to_string())
+    }
+
     fn post_condition() {
         std::fs::remove_file("vndis.log").unwrap_or(());
+        std::fs::remove_file("vndis.log.idx").unwrap_or(());
+        std::fs::remove_file("seen.idx").unwrap_or(());
+        std::fs::remove_file("memos.txt").unwrap_or(());
+        std::fs::remove_file("reminders.txt").unwrap_or(());
+        std::fs::remove_file("greetings.txt").unwrap_or(());
         std::fs::remove_file("config.json").unwrap_or(())
     }
 
     #[test]
     fn test_cmd_about() {
-        pre_condition();
-
-        let bot = super::KuuBot::new();
-        let log = super::log::IrcLog::new();
+        let (bot, mut chan, _) = bot_on_channel();
 
-        let response = bot.command_about(&"DoumanAsh".to_string(), &log);
+        let response = bot.direct_response(&"DoumanAsh".to_string(), super::acl::Role::Owner, &"Kuu: about".to_string(), &mut chan);
         assert!(match response {
-            super::BotResponse::Private(text) => text == format!("{} {}", &bot, &log),
+            super::BotResponse::Private(text) => text == format!("{} {} op=false", &bot, &chan),
             _ => false
         });
 
-        let response = bot.command_about(&"!DoumanAsh".to_string(), &log);
+        let response = bot.direct_response(&"DoumanAsh_x".to_string(), super::acl::Role::User, &"Kuu: status".to_string(), &mut chan);
         assert!(match response {
             super::BotResponse::Channel(text) => text == "It is for my master only",
             _ => false
@@ -537,36 +1613,191 @@ mod tests {
         post_condition();
     }
 
+    #[test]
+    fn test_dying_msg() {
+        pre_condition();
+
+        let bot = super::KuuBot::new();
+        assert!(!bot.stopping.get());
+
+        bot.send_response(super::BotResponse::DyingMsg, "#vndis", &"Douman".to_string(), super::acl::Role::Owner);
+        assert!(bot.stopping.get());
+        assert!(bot.outbox.len() == 1);
+
+        post_condition();
+    }
+
+    #[test]
+    fn test_log_outgoing() {
+        pre_condition();
+
+        let mut bot = super::KuuBot::new();
+        bot.nick = "Kuu".to_string();
+        let mut channels = std::collections::HashMap::new();
+        channels.insert("#vndis".to_string(), super::channel::Channel::new("#vndis"));
+
+        bot.send_msg("#vndis", "pong");
+        bot.send_msg("Douman", "secret");
+        bot.log_outgoing(&mut channels);
+
+        {
+            let chan = channels.get("#vndis").unwrap();
+            assert!(chan.log.len() == 1);
+            assert!(chan.log.back().unwrap().nickname() == "Kuu");
+            assert!(chan.log.back().unwrap().message() == "pong");
+            assert!(bot.private_log.borrow().is_none());
+            assert!(bot.outgoing.borrow().is_empty());
+        }
+
+        //Channel name is case insensitive.
+        bot.send_msg("#VNDIS", "PONG");
+        bot.log_outgoing(&mut channels);
+        bot.log_event(&super::Message::new(Some("Douman!douman@irc.net"), "JOIN", Some(vec!["#VnDis"]), None), &mut channels);
+        assert!(channels.get(&super::channel::key("#VnDis")).unwrap().log.len() == 3);
+
+        //Commands to NickServ are not logged even if private messages are.
+        bot.log_own_private = true;
+        bot.regain_method = Some("GHOST".to_string());
+        bot.config.nick_password = Some("password".to_string());
+        bot.regain_nick();
+        bot.verify_method = super::identity::Method::Status;
+        bot.verify("Douman", super::Message::new(Some("Douman!douman@irc.net"), "PRIVMSG", Some(vec!["Kuu"]), Some("status")));
+        assert!(bot.outgoing.borrow().is_empty());
+        bot.log_outgoing(&mut channels);
+        assert!(bot.private_log.borrow().is_none());
+
+        drop(channels);
+        post_condition();
+    }
+
+    #[test]
+    fn test_rejoin() {
+        pre_condition();
+
+        let mut bot = super::KuuBot::new();
+        bot.rejoin_delay_ms = 0;
+        let mut channels = std::collections::HashMap::new();
+        channels.insert("#vndis".to_string(), super::channel::Channel::new("#vndis"));
+        channels.insert("#other".to_string(), super::channel::Channel::new("#other"));
+
+        bot.schedule_join(channels.get_mut("#vndis").unwrap());
+        bot.schedule_join(channels.get_mut("#other").unwrap());
+        assert!(bot.next_rejoin.get().is_some());
+
+        //Channel which is joined or left meanwhile is skipped.
+        channels.get_mut("#other").unwrap().join();
+        bot.rejoin_channels(&mut channels);
+        assert!(bot.outbox.len() == 1);
+        assert!(bot.next_rejoin.get().is_none());
+
+        bot.schedule_join(channels.get_mut("#vndis").unwrap());
+        channels.remove("#vndis");
+        bot.rejoin_channels(&mut channels);
+        assert!(bot.outbox.len() == 1);
+
+        drop(channels);
+        std::fs::remove_file("other.log").unwrap_or(());
+        post_condition();
+    }
+
+    #[test]
+    fn test_migrate_logs() {
+        pre_condition();
+
+        let mut channels = std::collections::HashMap::new();
+        channels.insert("#nolog".to_string(), super::channel::Channel::with_backend("#nolog", super::log::Backend::Sqlite));
+
+        //Channel without log file is skipped instead of getting empty one.
+        assert!(super::KuuBot::migrate_logs(&channels) == "#nolog: there is no nolog.log\n");
+        assert!(std::fs::metadata("nolog.log").is_err());
+
+        drop(channels);
+        std::fs::remove_file(super::sqlite::DB_PATH).unwrap_or(());
+        post_condition();
+    }
+
+    #[test]
+    fn test_welcome() {
+        let (bot, chan, _) = bot_on_channel();
+
+        //Impostor with owner's nickname.
+        let message = ":Douman!evil@evil.net JOIN #vndis".parse::<super::Message>().unwrap();
+        bot.welcome(message, &chan);
+        assert!(bot.outbox.len() == 0);
+
+        let message = "@account=Douman :Douman_x!douman@irc.net JOIN #vndis".parse::<super::Message>().unwrap();
+        bot.welcome(message, &chan);
+        assert!(bot.outbox.len() == 1);
+        assert!(bot.outgoing.borrow()[0].1 == "Douman_x: Welcome, dear master!");
+
+        post_condition();
+    }
+
+    #[test]
+    fn test_config_changes() {
+        use std::collections::HashMap;
+        use super::Config;
+
+        let mut options = HashMap::new();
+        options.insert("quit_message".to_string(), "Good bye".to_string());
+        options.insert("trusted".to_string(), "".to_string());
+        let old = Config {
+            owners: Some(vec!["Douman".to_string()]),
+            options: Some(options.clone()),
+            ..Default::default()
+        };
+
+        assert!(super::KuuBot::config_changes(&old, &old).is_empty());
+
+        options.insert("quit_message".to_string(), "See you".to_string());
+        options.remove("trusted");
+        options.insert("ignored".to_string(), "*!*@*.spam".to_string());
+        let new = Config {
+            owners: Some(vec!["Douman".to_string(), "Kuu".to_string()]),
+            nickname: Some("Kuu".to_string()),
+            options: Some(options),
+            ..Default::default()
+        };
+
+        let changes = super::KuuBot::config_changes(&old, &new);
+        assert!(changes.len() == 5);
+        assert!(changes[0] == "owners: [\"Douman\"] => [\"Douman\", \"Kuu\"]");
+        assert!(changes[1] == "ignored: set to *!*@*.spam");
+        assert!(changes[2] == "quit_message: Good bye => See you");
+        assert!(changes[3] == "trusted:  is removed");
+        assert!(changes[4] == "server or nickname is changed, but it requires restart");
+    }
+
     #[test]
     fn test_cmd_google() {
-        let parts = vec!["Kuu:", "google"];
+        let parts: Vec<&str> = vec![];
         let response = super::KuuBot::command_google(&parts);
         assert!(match response {
             super::BotResponse::Channel(text) => text == "google nothing...?",
             _ => false
         });
 
-        let parts = vec!["Kuu:", "google", "vn", "sengoku", "hime"];
+        let parts = vec!["vn", "sengoku", "hime"];
         let response = super::KuuBot::command_google(&parts);
         assert!(match response {
-            super::BotResponse::Channel(text) => text == format!("http://lmgtfy.com/?q={}", parts[2..].join("+")),
+            super::BotResponse::Channel(text) => text == format!("http://lmgtfy.com/?q={}", parts.join("+")),
             _ => false
         });
     }
 
     #[test]
     fn test_cmd_grep() {
-        let parts = vec!["Kuu:", "grep"];
+        let parts: Vec<&str> = vec![];
         let response = super::KuuBot::command_grep(&parts);
         assert!(match response {
             super::BotResponse::Channel(text) => text == "what? Nothing...?",
             _ => false
         });
 
-        let parts = vec!["Kuu:", "grep", "vn", "Sengoku", "Hime"];
+        let parts = vec!["vn", "Sengoku", "Hime"];
         let response = super::KuuBot::command_grep(&parts);
         assert!(match response {
-            super::BotResponse::Channel(text) => text == format!("vndb: https://vndb.org/v/all?q={};fil=tagspoil-0;o=d;s=rel", parts[3..].join("+")),
+            super::BotResponse::Channel(text) => text == format!("vndb: https://vndb.org/v/all?q={};fil=tagspoil-0;o=d;s=rel", parts[1..].join("+")),
             _ => false
         });
     }
@@ -627,11 +1858,13 @@ mod tests {
         assert!(super::KuuBot::parse_filter_time("20d").is_ok());
         assert!(super::KuuBot::parse_filter_time("20h").is_ok());
         assert!(super::KuuBot::parse_filter_time("0h").is_ok());
+        assert!(super::KuuBot::parse_filter_time("2h30m").is_ok());
 
         assert!(super::KuuBot::parse_filter_time("-20h").is_err());
         assert!(super::KuuBot::parse_filter_time("0Gsdasdsa").is_err());
         assert!(super::KuuBot::parse_filter_time("G1").is_err());
         assert!(super::KuuBot::parse_filter_time("5").is_err());
+        assert!(super::KuuBot::parse_filter_time("99999999999d").is_err());
     }
 
     #[test]
@@ -677,51 +1910,114 @@ mod tests {
     }
 
     #[test]
-    fn test_simple_responses() {
+    fn test_log_search() {
         pre_condition();
 
-        let bot = super::KuuBot::new();
         let mut log = super::log::IrcLog::new();
+        log.add(super::log::IrcEntry::new("Kuu".to_string(), "Tadaima".to_string()));
+        log.add(super::log::IrcEntry::new("Douman".to_string(), "okaeri".to_string()));
+        log.add(super::log::IrcEntry::new("Kuu".to_string(), "tadaima again".to_string()));
+
+        if let super::BotResponse::PrivateMulti(log_lines) = super::KuuBot::command_log_search(&mut log, &["tadaima"]) {
+            let lines_array: Vec<&str> = log_lines.lines().collect();
+            assert!(lines_array.len() == 3);
+            assert!(lines_array[0] == "Found 2 messages");
+            assert!(lines_array[1].ends_with("<Kuu> Tadaima"));
+            assert!(lines_array[2].ends_with("<Kuu> tadaima again"));
+        }
+        else {
+            assert!(false);
+        }
+
+        assert!(match super::KuuBot::command_log_search(&mut log, &["nya"]) {
+            super::BotResponse::Private(text) => text == "Nothing is found for: nya",
+            _                                 => false,
+        });
+        assert!(match super::KuuBot::command_log_search(&mut log, &[]) {
+            super::BotResponse::Channel(_) => true,
+            _                              => false,
+        });
+
+        drop(log);
 
-        let default_nick = "Someone".to_string();
+        post_condition();
+    }
+
+    #[test]
+    fn test_cmd_log_dump() {
+        let (bot, mut chan, _) = bot_on_channel();
+        let nickname = "Douman".to_string();
+        let usr_msg = "Kuu: log dump".to_string();
+        let message = super::Message::new(Some("Douman!douman@irc.net"), "PRIVMSG", Some(vec!["#vndis"]), Some(&usr_msg));
+
+        //Owner's nickname grants nothing until NickServ confirms it.
+        let role = bot.role(&message);
+        assert!(role == super::acl::Role::User);
+        assert!(match bot.direct_response(&nickname, role, &usr_msg, &mut chan) {
+            super::BotResponse::Unverified => true,
+            _                              => false,
+        });
+
+        bot.identities.borrow_mut().resolve(&nickname, None);
+        assert!(match bot.direct_response(&nickname, bot.role(&message), &usr_msg, &mut chan) {
+            super::BotResponse::Channel(text) => text == "Log dump is for trusted only",
+            _                                 => false,
+        });
+
+        //Other log commands are for everyone.
+        assert!(match bot.direct_response(&nickname, role, &"Kuu: log len".to_string(), &mut chan) {
+            super::BotResponse::Private(_) => true,
+            _                              => false,
+        });
+
+        bot.identities.borrow_mut().forget(&nickname);
+        bot.identities.borrow_mut().resolve(&nickname, Some("Douman".to_string()));
+        assert!(bot.role(&message) == super::acl::Role::Owner);
+
+        post_condition();
+    }
+
+    #[test]
+    fn test_simple_responses() {
+        let (bot, mut chan, default_nick) = bot_on_channel();
 
         let usr_msg = "Kuu: ping".to_string();
-        let response = bot.direct_response(&default_nick, &usr_msg, &mut log);
+        let response = bot.direct_response(&default_nick, super::acl::Role::User, &usr_msg, &mut chan);
         assert!(match response {
             super::BotResponse::Channel(text) => text == "pong",
             _ => false
         });
 
         let usr_msg = "Kuu: asdasdasfdsgfdsgdfgdfg".to_string();
-        let response = bot.direct_response(&default_nick, &usr_msg, &mut log);
+        let response = bot.direct_response(&default_nick, super::acl::Role::User, &usr_msg, &mut chan);
         assert!(match response {
             super::BotResponse::Channel(text) => text == "...",
             _ => false
         });
 
         let usr_msg = "Kuu: huiping".to_string();
-        let response = bot.direct_response(&default_nick, &usr_msg, &mut log);
+        let response = bot.direct_response(&default_nick, super::acl::Role::User, &usr_msg, &mut chan);
         assert!(match response {
             super::BotResponse::Channel(text) => text == "死になさいゴミムシ",
             _ => false
         });
 
         let usr_msg = "!ping".to_string();
-        let response = bot.indirect_response(&default_nick, &usr_msg, &mut log);
+        let response = bot.indirect_response(&default_nick, super::acl::Role::User, &usr_msg, &mut chan);
         assert!(match response {
             super::BotResponse::Channel(text) => text == "pong",
             _ => false
         });
 
         let usr_msg = "!huiping".to_string();
-        let response = bot.indirect_response(&default_nick, &usr_msg, &mut log);
+        let response = bot.indirect_response(&default_nick, super::acl::Role::User, &usr_msg, &mut chan);
         assert!(match response {
             super::BotResponse::Channel(text) => text == "死になさいゴミムシ",
             _ => false
         });
 
         let usr_msg = "Kuu: tadaima".to_string();
-        let response = bot.indirect_response(&default_nick, &usr_msg, &mut log);
+        let response = bot.indirect_response(&default_nick, super::acl::Role::User, &usr_msg, &mut chan);
         assert!(match response {
             super::BotResponse::Channel(text) => text == "okaeri",
             _ => false
@@ -729,4 +2025,132 @@ mod tests {
 
         post_condition();
     }
+
+    #[test]
+    fn test_cmd_who() {
+        let (bot, mut chan, default_nick) = bot_on_channel();
+
+        chan.roster.join("Douman!douman@irc.net", 0);
+        chan.roster.mode("+o", &["Douman"]);
+        chan.roster.names("Kuu");
+        let usr_msg = "!users".to_string();
+        let response = bot.indirect_response(&default_nick, super::acl::Role::User, &usr_msg, &mut chan);
+        assert!(match response {
+            super::BotResponse::Private(text) => text == "2 users on #vndis: @Douman Kuu",
+            _ => false
+        });
+
+        let usr_msg = "Kuu: who nobody".to_string();
+        let response = bot.direct_response(&default_nick, super::acl::Role::User, &usr_msg, &mut chan);
+        assert!(match response {
+            super::BotResponse::Channel(text) => text == "there is no nobody on #vndis",
+            _ => false
+        });
+
+        post_condition();
+    }
+
+    #[test]
+    fn test_cmd_seen() {
+        let (bot, mut chan, default_nick) = bot_on_channel();
+
+        let usr_msg = "!seen nobody".to_string();
+        let response = bot.indirect_response(&default_nick, super::acl::Role::User, &usr_msg, &mut chan);
+        assert!(match response {
+            super::BotResponse::Channel(text) => text == "I have never seen nobody",
+            _ => false
+        });
+
+        post_condition();
+    }
+
+    #[test]
+    fn test_cmd_tell() {
+        let (bot, mut chan, default_nick) = bot_on_channel();
+
+        let usr_msg = "!tell Douman Nya nya".to_string();
+        let response = bot.indirect_response(&default_nick, super::acl::Role::User, &usr_msg, &mut chan);
+        assert!(match response {
+            super::BotResponse::Channel(text) => text == "I'll tell Douman when they are around. Memo #1",
+            _ => false
+        });
+
+        let usr_msg = "Kuu: memo cancel 1".to_string();
+        let response = bot.direct_response(&default_nick, super::acl::Role::User, &usr_msg, &mut chan);
+        assert!(match response {
+            super::BotResponse::Channel(text) => text == "memo #1 is cancelled",
+            _ => false
+        });
+
+        post_condition();
+    }
+
+    #[test]
+    fn test_cmd_remind() {
+        let (bot, mut chan, default_nick) = bot_on_channel();
+
+        let usr_msg = "!remind me in 2h30m to Sleep".to_string();
+        let response = bot.indirect_response(&default_nick, super::acl::Role::User, &usr_msg, &mut chan);
+        assert!(match response {
+            super::BotResponse::Channel(text) => text == "I'll remind you in 2h 30m. Reminder #1",
+            _ => false
+        });
+        assert!(bot.scheduler.borrow().list(&default_nick)[0].text == "Sleep");
+
+        let usr_msg = "Kuu: remind me at nya".to_string();
+        let response = bot.direct_response(&default_nick, super::acl::Role::User, &usr_msg, &mut chan);
+        assert!(match response {
+            super::BotResponse::Channel(text) => text == "I don't understand when. Try e.g. in 2h30m or at 18:30",
+            _ => false
+        });
+
+        let usr_msg = "Kuu: remind delete 1".to_string();
+        let response = bot.direct_response(&default_nick, super::acl::Role::User, &usr_msg, &mut chan);
+        assert!(match response {
+            super::BotResponse::Channel(text) => text == "reminder #1 is deleted",
+            _ => false
+        });
+
+        //Reminder is not lost when bot is not on its channel.
+        let mut channels = std::collections::HashMap::new();
+        channels.insert("#vndis".to_string(), chan);
+        bot.scheduler.borrow_mut().add(&default_nick, "#vndis", 100, "Sleep").unwrap();
+        bot.scheduler.borrow_mut().add(&default_nick, "#gone", 100, "Wake up").unwrap();
+        bot.fire_reminders(&channels);
+        channels.get_mut("#vndis").unwrap().joined = true;
+        bot.scheduler.borrow_mut().add(&default_nick, "#vndis", 100, "Sleep").unwrap();
+        bot.fire_reminders(&channels);
+
+        let outgoing = bot.outgoing.borrow();
+        assert!(outgoing.len() == 3);
+        assert!(outgoing[0].0 == default_nick && outgoing[0].1.starts_with("reminder from #vndis: Sleep"));
+        assert!(outgoing[1].0 == default_nick && outgoing[1].1.starts_with("reminder from #gone: Wake up"));
+        assert!(outgoing[2].0 == "#vndis" && outgoing[2].1.starts_with("Someone: reminder: Sleep"));
+        drop(outgoing);
+        drop(channels);
+
+        post_condition();
+    }
+
+    #[test]
+    fn test_cmd_greet() {
+        let (bot, mut chan, default_nick) = bot_on_channel();
+
+        let usr_msg = "Kuu: greet Nya nya!".to_string();
+        let response = bot.direct_response(&default_nick, super::acl::Role::User, &usr_msg, &mut chan);
+        assert!(match response {
+            super::BotResponse::Channel(text) => text == "I'll greet you on #vndis from now on",
+            _ => false
+        });
+        assert!(bot.greetings.borrow().find("Someone!some@irc.net", "#vndis").unwrap().text == "Nya nya!");
+
+        let usr_msg = "Kuu: greet off".to_string();
+        let response = bot.direct_response(&default_nick, super::acl::Role::User, &usr_msg, &mut chan);
+        assert!(match response {
+            super::BotResponse::Channel(text) => text == "I won't greet you anymore",
+            _ => false
+        });
+
+        post_condition();
+    }
 }
@@ -0,0 +1,180 @@
+//! Offline messages
+
+use std::fmt;
+
+use utils;
+
+///Message which waits for recipient to return.
+#[derive(Clone, Debug)]
+pub struct Memo {
+    pub id: u64,
+    pub from: String,
+    pub to: String,
+    ///Time in seconds.
+    pub time: i64,
+    pub channel: String,
+    pub text: String
+}
+
+impl Memo {
+    ///Parses line of state file.
+    fn parse(line: &str) -> Option<Memo> {
+        let parts: Vec<&str> = line.splitn(6, '\t').collect();
+        if parts.len() < 6 {
+            return None;
+        }
+
+        match (parts[0].parse::<u64>(), parts[3].parse::<i64>()) {
+            (Ok(id), Ok(time)) => Some(Memo {
+                id: id,
+                from: parts[1].to_string(),
+                to: parts[2].to_string(),
+                time: time,
+                channel: parts[4].to_string(),
+                text: parts[5].to_string()
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Memo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}\t{}\t{}\t{}\t{}\t{}", self.id, self.from, self.to, self.time, self.channel, utils::state_field(&self.text))
+    }
+}
+
+///Persistent storage of memos.
+pub struct Memos {
+    path: String,
+    inner: Vec<Memo>,
+    next_id: u64,
+    ///Max number of pending memos from one sender.
+    limit: usize
+}
+
+impl Memos {
+    ///Loads memos from file, if it exists.
+    pub fn open(path: &str, limit: usize) -> Memos {
+        let inner = utils::load_state(path, Memo::parse);
+
+        Memos {
+            path: path.to_string(),
+            next_id: inner.iter().map(|memo| memo.id).max().unwrap_or(0) + 1,
+            inner: inner,
+            limit: limit
+        }
+    }
+
+    #[inline(always)]
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+    }
+
+    ///Stores memo.
+    ///
+    ///Returns id of memo or error if sender has too many pending memos.
+    pub fn add(&mut self, from: &str, to: &str, channel: &str, text: &str, now: i64) -> Result<u64, String> {
+        if self.list(from).len() >= self.limit {
+            return Err(format!("you already have {} pending memos", self.limit));
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.inner.push(Memo {
+            id: id,
+            from: from.to_string(),
+            to: to.to_string(),
+            time: now,
+            channel: channel.to_string(),
+            text: text.to_string()
+        });
+        self.save();
+
+        Ok(id)
+    }
+
+    ///Returns pending memos of sender.
+    pub fn list(&self, from: &str) -> Vec<&Memo> {
+        let from = from.to_lowercase();
+        self.inner.iter().filter(|memo| memo.from.to_lowercase() == from).collect()
+    }
+
+    ///Cancels sender's memo.
+    ///
+    ///Returns false if there is no such memo.
+    pub fn cancel(&mut self, from: &str, id: u64) -> bool {
+        let from = from.to_lowercase();
+        match self.inner.iter().position(|memo| memo.id == id && memo.from.to_lowercase() == from) {
+            Some(idx) => {
+                self.inner.remove(idx);
+                self.save();
+                true
+            },
+            None => false,
+        }
+    }
+
+    ///Removes and returns memos for recipient.
+    pub fn take(&mut self, to: &str) -> Vec<Memo> {
+        let to = to.to_lowercase();
+        if !self.inner.iter().any(|memo| memo.to.to_lowercase() == to) {
+            return Vec::new();
+        }
+
+        let (result, rest): (Vec<Memo>, Vec<Memo>) = self.inner.drain(..).partition(|memo| memo.to.to_lowercase() == to);
+        self.inner = rest;
+        self.save();
+
+        result
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    ///Writes undelivered memos to file in order they are left.
+    pub fn save(&self) {
+        utils::save_state(&self.path, self.inner.iter());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std;
+
+    #[test]
+    fn test_memos() {
+        let path = "test_memos.txt";
+        {
+            let mut memos = Memos::open(path, 2);
+            assert!(memos.len() == 0);
+
+            assert!(memos.add("Douman", "Kuu", "#vndis", "nya\tnya", 100) == Ok(1));
+            assert!(memos.add("douman", "Someone", "#vndis", "hello", 200) == Ok(2));
+            assert!(memos.add("Douman", "Kuu", "#vndis", "too much", 300).is_err());
+            assert!(memos.add("Someone", "Kuu", "#vndis", "hi", 300) == Ok(3));
+
+            assert!(memos.list("DOUMAN").len() == 2);
+            assert!(!memos.cancel("Someone", 2));
+            assert!(memos.cancel("Douman", 2));
+            assert!(memos.list("Douman").len() == 1);
+        }
+
+        let mut memos = Memos::open(path, 2);
+        assert!(memos.len() == 2);
+        assert!(memos.add("Douman", "Kuu", "#vndis", "again", 400) == Ok(4));
+
+        let delivered = memos.take("kuu");
+        assert!(delivered.len() == 3);
+        assert!(delivered[0].from == "Douman");
+        assert!(delivered[0].text == "nya nya");
+        assert!(delivered[0].time == 100);
+        assert!(memos.take("Kuu").is_empty());
+        assert!(memos.len() == 0);
+
+        std::fs::remove_file(path).unwrap_or(());
+    }
+}
@@ -0,0 +1,106 @@
+//! Own nickname management
+
+///Primary and alternative nicknames of bot.
+pub struct Nicks {
+    primary: String,
+    alternatives: Vec<String>,
+    ///Index of next alternative nickname to try.
+    next: usize,
+    ///Interval in seconds between attempts to regain primary nickname. Zero disables them.
+    interval: i64,
+    last_regain: i64
+}
+
+impl Nicks {
+    #[inline(always)]
+    pub fn new(primary: &str, alternatives: Vec<String>, interval: i64) -> Nicks {
+        Nicks {
+            primary: primary.to_string(),
+            alternatives: alternatives,
+            next: 0,
+            interval: interval,
+            last_regain: 0
+        }
+    }
+
+    #[inline]
+    pub fn set(&mut self, primary: &str, alternatives: Vec<String>, interval: i64) {
+        self.primary = primary.to_string();
+        self.alternatives = alternatives;
+        self.interval = interval;
+    }
+
+    #[inline(always)]
+    pub fn primary(&self) -> &str {
+        &self.primary
+    }
+
+    ///Returns next nickname to try when current one is in use.
+    ///
+    ///When all alternatives are taken, primary nickname with numeric suffix is used.
+    pub fn next_alt(&mut self) -> String {
+        let nick = match self.alternatives.get(self.next) {
+            Some(nick) => nick.clone(),
+            None       => format!("{}_{}", self.primary, self.next + 1),
+        };
+
+        self.next += 1;
+        nick
+    }
+
+    #[inline(always)]
+    ///Starts cycle of alternatives from the beginning e.g. on reconnect.
+    pub fn reset(&mut self) {
+        self.next = 0;
+    }
+
+    ///Returns whether it is time to try to regain primary nickname.
+    pub fn should_regain(&mut self, current: &str, now: i64) -> bool {
+        if self.interval == 0 || current.is_empty() || current.to_lowercase() == self.primary.to_lowercase() {
+            return false;
+        }
+
+        if now - self.last_regain < self.interval {
+            return false;
+        }
+
+        self.last_regain = now;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_alt() {
+        let mut nicks = Nicks::new("Kuu", vec!["Kuu_1".to_string(), "Kuu_2".to_string()], 300);
+
+        assert!(nicks.next_alt() == "Kuu_1");
+        assert!(nicks.next_alt() == "Kuu_2");
+        assert!(nicks.next_alt() == "Kuu_3");
+        assert!(nicks.next_alt() == "Kuu_4");
+
+        nicks.reset();
+        assert!(nicks.next_alt() == "Kuu_1");
+
+        let mut nicks = Nicks::new("Kuu", vec![], 300);
+        assert!(nicks.next_alt() == "Kuu_1");
+        assert!(nicks.next_alt() == "Kuu_2");
+    }
+
+    #[test]
+    fn test_should_regain() {
+        let mut nicks = Nicks::new("Kuu", vec![], 300);
+
+        assert!(!nicks.should_regain("", 1000));
+        assert!(!nicks.should_regain("KUU", 1000));
+        assert!(nicks.should_regain("Kuu_1", 1000));
+        assert!(!nicks.should_regain("Kuu_1", 1200));
+        assert!(nicks.should_regain("Kuu_1", 1300));
+
+        let mut nicks = Nicks::new("Kuu", vec![], 0);
+        assert!(!nicks.should_regain("Kuu_1", 1000));
+    }
+}
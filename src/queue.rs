@@ -0,0 +1,288 @@
+//! Rate-limited queue of outgoing messages
+
+extern crate time;
+
+use irc::client::prelude::Message;
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, Condvar};
+use std::time::Duration;
+use std::thread;
+
+///Priority of outgoing message.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Priority {
+    Normal,
+    ///Replies to owners go ahead of everything else.
+    High
+}
+
+///Token bucket which allows `burst` messages at once and then `refill` messages per second.
+pub struct TokenBucket {
+    burst: f64,
+    refill: f64,
+    tokens: f64,
+    last: f64
+}
+
+impl TokenBucket {
+    #[inline(always)]
+    ///Creates full bucket.
+    pub fn new(burst: f64, refill: f64, now: f64) -> TokenBucket {
+        TokenBucket {
+            burst: burst,
+            refill: refill,
+            tokens: burst,
+            last: now
+        }
+    }
+
+    #[inline]
+    pub fn set_rate(&mut self, burst: f64, refill: f64) {
+        self.burst = burst;
+        self.refill = refill;
+        self.tokens = self.tokens.min(burst);
+    }
+
+    ///Takes one token.
+    ///
+    ///Returns number of seconds to wait if there is no token available yet.
+    pub fn take(&mut self, now: f64) -> Option<f64> {
+        self.tokens = (self.tokens + (now - self.last) * self.refill).min(self.burst);
+        self.last = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            None
+        }
+        else {
+            Some((1.0 - self.tokens) / self.refill)
+        }
+    }
+}
+
+///Queue that serves targets in round-robin.
+struct FairQueue {
+    order: VecDeque<String>,
+    inner: HashMap<String, VecDeque<Message>>
+}
+
+impl FairQueue {
+    #[inline(always)]
+    fn new() -> FairQueue {
+        FairQueue {
+            order: VecDeque::new(),
+            inner: HashMap::new()
+        }
+    }
+
+    fn push(&mut self, target: &str, message: Message) {
+        let target = target.to_lowercase();
+        if !self.inner.contains_key(&target) {
+            self.order.push_back(target.clone());
+        }
+        self.inner.entry(target).or_insert(VecDeque::new()).push_back(message);
+    }
+
+    fn pop(&mut self) -> Option<Message> {
+        let target = match self.order.pop_front() {
+            Some(target) => target,
+            None         => return None,
+        };
+
+        let (message, is_empty) = {
+            let messages = self.inner.get_mut(&target).unwrap();
+            (messages.pop_front(), messages.is_empty())
+        };
+
+        if is_empty {
+            self.inner.remove(&target);
+        }
+        else {
+            self.order.push_back(target);
+        }
+
+        message
+    }
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.inner.values().fold(0, |acc, messages| acc + messages.len())
+    }
+}
+
+///Queue of outgoing messages with priorities.
+pub struct Queue {
+    high: FairQueue,
+    normal: FairQueue,
+    bucket: TokenBucket,
+    ///Whether popped message is being sent right now.
+    in_flight: bool
+}
+
+impl Queue {
+    #[inline(always)]
+    pub fn new(burst: f64, refill: f64) -> Queue {
+        Queue {
+            high: FairQueue::new(),
+            normal: FairQueue::new(),
+            bucket: TokenBucket::new(burst, refill, time::precise_time_s()),
+            in_flight: false
+        }
+    }
+
+    #[inline]
+    pub fn push(&mut self, target: &str, message: Message, priority: Priority) {
+        match priority {
+            Priority::High   => self.high.push(target, message),
+            Priority::Normal => self.normal.push(target, message),
+        }
+    }
+
+    #[inline]
+    pub fn pop(&mut self) -> Option<Message> {
+        self.high.pop().or_else(|| self.normal.pop())
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.high.len() + self.normal.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.high.order.is_empty() && self.normal.order.is_empty()
+    }
+}
+
+///Shared handle to queue of outgoing messages.
+#[derive(Clone)]
+pub struct Outbox {
+    inner: Arc<(Mutex<Queue>, Condvar)>
+}
+
+impl Outbox {
+    #[inline(always)]
+    pub fn new(burst: f64, refill: f64) -> Outbox {
+        Outbox {
+            inner: Arc::new((Mutex::new(Queue::new(burst, refill)), Condvar::new()))
+        }
+    }
+
+    ///Puts message to target in queue.
+    pub fn push(&self, target: &str, message: Message, priority: Priority) {
+        let &(ref queue, ref cvar) = &*self.inner;
+        queue.lock().unwrap().push(target, message, priority);
+        cvar.notify_all();
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.inner.0.lock().unwrap().len()
+    }
+
+    #[inline]
+    ///Changes rate limit.
+    pub fn set_rate(&self, burst: f64, refill: f64) {
+        self.inner.0.lock().unwrap().bucket.set_rate(burst, refill);
+    }
+
+    ///Waits until all messages are sent.
+    ///
+    ///Returns false on timeout.
+    pub fn flush(&self, timeout_ms: u64) -> bool {
+        let &(ref queue, ref cvar) = &*self.inner;
+        let deadline = time::precise_time_s() + timeout_ms as f64 / 1000.0;
+        let mut queue = queue.lock().unwrap();
+
+        while !queue.is_empty() || queue.in_flight {
+            let left = deadline - time::precise_time_s();
+            if left <= 0.0 {
+                return false;
+            }
+
+            queue = cvar.wait_timeout(queue, Duration::from_millis((left * 1000.0) as u64)).unwrap().0;
+        }
+
+        true
+    }
+
+    ///Starts worker thread which sends messages with respect to rate limit.
+    pub fn start<F: Fn(Message) + Send + 'static>(&self, send: F) -> thread::JoinHandle<()> {
+        let inner = self.inner.clone();
+
+        thread::spawn(move || {
+            let &(ref queue, ref cvar) = &*inner;
+            loop {
+                let message = {
+                    let mut queue = queue.lock().unwrap();
+                    while queue.is_empty() {
+                        queue = cvar.wait(queue).unwrap();
+                    }
+
+                    match queue.bucket.take(time::precise_time_s()) {
+                        Some(wait) => Err(wait),
+                        None       => {
+                            queue.in_flight = true;
+                            Ok(queue.pop().unwrap())
+                        }
+                    }
+                };
+
+                match message {
+                    Ok(message) => {
+                        send(message);
+                        queue.lock().unwrap().in_flight = false;
+                        cvar.notify_all();
+                    },
+                    Err(wait) => thread::sleep(Duration::from_millis((wait * 1000.0) as u64 + 1)),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use irc::client::prelude::Message;
+
+    fn privmsg(target: &str, text: &str) -> Message {
+        Message::new(None, "PRIVMSG", Some(vec![target]), Some(text))
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let mut bucket = TokenBucket::new(2.0, 0.5, 0.0);
+
+        assert!(bucket.take(0.0).is_none());
+        assert!(bucket.take(0.0).is_none());
+        assert!(bucket.take(0.0) == Some(2.0));
+        assert!(bucket.take(1.0) == Some(1.0));
+        assert!(bucket.take(2.0).is_none());
+        //Bucket never overflows burst.
+        assert!(bucket.take(100.0).is_none());
+        assert!(bucket.take(100.0).is_none());
+        assert!(bucket.take(100.0).is_some());
+    }
+
+    #[test]
+    fn test_queue_fairness() {
+        let mut queue = Queue::new(1.0, 1.0);
+
+        queue.push("Kuu", privmsg("Kuu", "1"), Priority::Normal);
+        queue.push("Kuu", privmsg("Kuu", "2"), Priority::Normal);
+        queue.push("Kuu", privmsg("Kuu", "3"), Priority::Normal);
+        queue.push("#vndis", privmsg("#vndis", "a"), Priority::Normal);
+        queue.push("Douman", privmsg("Douman", "master"), Priority::High);
+
+        assert!(queue.len() == 5);
+
+        let mut order = Vec::new();
+        while let Some(message) = queue.pop() {
+            order.push(message.suffix.unwrap());
+        }
+        assert!(order == vec!["master", "1", "a", "2", "3"]);
+        assert!(queue.is_empty());
+    }
+}
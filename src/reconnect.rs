@@ -0,0 +1,136 @@
+//! Reconnect policy
+
+use std::cmp;
+use std::fmt;
+
+///Exponential backoff with jitter and failure budget.
+pub struct Backoff {
+    base_ms: u64,
+    max_ms: u64,
+    ///Max number of consecutive attempts. Zero means unlimited.
+    max_attempts: u32,
+    ///Number of consecutive attempts since last successful registration.
+    attempt: u32,
+    total_attempts: u64,
+    reconnects: u64,
+    last_error: Option<String>
+}
+
+impl Backoff {
+    #[inline(always)]
+    pub fn new(base_ms: u64, max_ms: u64, max_attempts: u32) -> Backoff {
+        Backoff {
+            base_ms: base_ms,
+            max_ms: max_ms,
+            max_attempts: max_attempts,
+            attempt: 0,
+            total_attempts: 0,
+            reconnects: 0,
+            last_error: None
+        }
+    }
+
+    #[inline]
+    pub fn set_limits(&mut self, base_ms: u64, max_ms: u64, max_attempts: u32) {
+        self.base_ms = base_ms;
+        self.max_ms = max_ms;
+        self.max_attempts = max_attempts;
+    }
+
+    ///Returns delay in ms before next attempt.
+    ///
+    ///Jitter is a random value in range [0, 1) which spreads the second half of delay.
+    pub fn next_delay(&mut self, jitter: f64) -> u64 {
+        let delay = cmp::min(self.max_ms, self.base_ms.saturating_mul(1u64 << cmp::min(self.attempt, 32)));
+
+        self.attempt += 1;
+        self.total_attempts += 1;
+
+        delay / 2 + (delay as f64 / 2.0 * jitter) as u64
+    }
+
+    #[inline(always)]
+    ///Returns whether failure budget is exhausted.
+    pub fn is_exhausted(&self) -> bool {
+        self.max_attempts > 0 && self.attempt >= self.max_attempts
+    }
+
+    #[inline(always)]
+    ///Records that connection is established.
+    pub fn connected(&mut self) {
+        self.reconnects += 1;
+    }
+
+    #[inline(always)]
+    ///Records that bot is registered on server, so next reconnect starts from base delay.
+    pub fn registered(&mut self) {
+        self.attempt = 0;
+    }
+
+    #[inline(always)]
+    pub fn failure(&mut self, error: String) {
+        self.last_error = Some(error);
+    }
+
+    #[cfg(test)]
+    #[inline(always)]
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+}
+
+impl fmt::Display for Backoff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        try!(write!(f, "Reconnect(attempt={}, total_attempts={}, reconnects={}", self.attempt, self.total_attempts, self.reconnects));
+        if let Some(ref error) = self.last_error {
+            try!(write!(f, ", last_error={}", error));
+        }
+        write!(f, ")")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_delay() {
+        let mut backoff = Backoff::new(1000, 10000, 0);
+
+        assert!(backoff.next_delay(0.0) == 500);
+        assert!(backoff.next_delay(0.0) == 1000);
+        assert!(backoff.next_delay(0.5) == 3000);
+        assert!(backoff.next_delay(0.0) == 4000);
+        //Max delay is reached
+        assert!(backoff.next_delay(0.0) == 5000);
+        assert!(backoff.next_delay(0.99) == 9950);
+        assert!(backoff.attempt() == 6);
+
+        for _ in 0..100 {
+            assert!(backoff.next_delay(0.99) <= 10000);
+        }
+        assert!(!backoff.is_exhausted());
+
+        backoff.registered();
+        assert!(backoff.attempt() == 0);
+        assert!(backoff.next_delay(0.0) == 500);
+    }
+
+    #[test]
+    fn test_backoff_budget() {
+        let mut backoff = Backoff::new(1000, 10000, 2);
+
+        assert!(!backoff.is_exhausted());
+        backoff.next_delay(0.0);
+        backoff.failure("Connection refused".to_string());
+        assert!(!backoff.is_exhausted());
+        backoff.next_delay(0.0);
+        assert!(backoff.is_exhausted());
+        assert!(format!("{}", backoff) == "Reconnect(attempt=2, total_attempts=2, reconnects=0, last_error=Connection refused)");
+
+        backoff.connected();
+        backoff.registered();
+        assert!(!backoff.is_exhausted());
+        assert!(format!("{}", backoff) == "Reconnect(attempt=0, total_attempts=2, reconnects=1, last_error=Connection refused)");
+    }
+}
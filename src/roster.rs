@@ -0,0 +1,253 @@
+//! Channel members tracking
+
+use std::collections::{HashMap, HashSet};
+
+///Prefixes of channel privileges from highest to lowest.
+const PREFIXES: &'static str = "~&@%+";
+
+///Returns NAMES prefix of user's channel mode.
+fn mode_prefix(mode: char) -> Option<char> {
+    match mode {
+        'q' => Some('~'),
+        'a' => Some('&'),
+        'o' => Some('@'),
+        'h' => Some('%'),
+        'v' => Some('+'),
+        _   => None,
+    }
+}
+
+///Channel member.
+#[derive(Clone, Debug)]
+pub struct Member {
+    pub nick: String,
+    ///Privilege prefixes ordered from highest.
+    pub prefixes: String,
+    ///Full prefix `nick!user@host` if known.
+    pub hostmask: Option<String>,
+    ///Time(in seconds) when member is seen to join.
+    pub joined: Option<i64>
+}
+
+impl Member {
+    #[inline(always)]
+    fn new(nick: &str) -> Member {
+        Member {
+            nick: nick.to_string(),
+            prefixes: String::new(),
+            hostmask: None,
+            joined: None
+        }
+    }
+
+    #[inline]
+    ///Returns highest privilege prefix or empty string.
+    pub fn prefix(&self) -> &str {
+        match self.prefixes.chars().next() {
+            Some(ch) => &self.prefixes[..ch.len_utf8()],
+            None     => "",
+        }
+    }
+
+    #[inline]
+    pub fn is_op(&self) -> bool {
+        self.prefixes.chars().any(|ch| ch == '~' || ch == '&' || ch == '@')
+    }
+
+    fn set_prefix(&mut self, prefix: char, is_set: bool) {
+        let mut prefixes: Vec<char> = self.prefixes.chars().filter(|ch| *ch != prefix).collect();
+        if is_set {
+            prefixes.push(prefix);
+        }
+        self.prefixes = PREFIXES.chars().filter(|ch| prefixes.contains(ch)).collect();
+    }
+}
+
+///Members of channel.
+pub struct Roster {
+    members: HashMap<String, Member>,
+    ///Members listed by NAMES reply which is in progress.
+    names: Option<HashSet<String>>
+}
+
+impl Roster {
+    #[inline(always)]
+    pub fn new() -> Roster {
+        Roster {
+            members: HashMap::new(),
+            names: None
+        }
+    }
+
+    #[inline(always)]
+    fn key(nick: &str) -> String {
+        nick.to_lowercase()
+    }
+
+    ///Handles part of NAMES reply(RPL_NAMREPLY).
+    ///
+    ///Names can have privilege prefixes and hostmasks i.e. `@nick` or `@nick!user@host`.
+    pub fn names(&mut self, names: &str) {
+        let mut seen = self.names.take().unwrap_or(HashSet::new());
+
+        for name in names.split_whitespace() {
+            let prefixes: String = name.chars().take_while(|ch| PREFIXES.contains(*ch)).collect();
+            let name = &name[prefixes.len()..];
+            let nick = &name[..name.find('!').unwrap_or(name.len())];
+            if nick.is_empty() {
+                continue;
+            }
+
+            let member = self.members.entry(Roster::key(nick)).or_insert(Member::new(nick));
+            member.prefixes = PREFIXES.chars().filter(|ch| prefixes.contains(*ch)).collect();
+            if nick.len() < name.len() {
+                member.hostmask = Some(name.to_string());
+            }
+            seen.insert(Roster::key(nick));
+        }
+
+        self.names = Some(seen);
+    }
+
+    ///Handles end of NAMES reply(RPL_ENDOFNAMES), so that members who are not listed are removed.
+    pub fn end_names(&mut self) {
+        if let Some(seen) = self.names.take() {
+            let gone: Vec<String> = self.members.keys().filter(|key| !seen.contains(*key)).cloned().collect();
+            for key in gone {
+                self.members.remove(&key);
+            }
+        }
+    }
+
+    ///Adds member who joined at `now`.
+    pub fn join(&mut self, hostmask: &str, now: i64) {
+        let nick = &hostmask[..hostmask.find('!').unwrap_or(hostmask.len())];
+        let mut member = Member::new(nick);
+        member.hostmask = Some(hostmask.to_string());
+        member.joined = Some(now);
+
+        self.members.insert(Roster::key(nick), member);
+    }
+
+    #[inline]
+    ///Removes member on PART, KICK or QUIT.
+    ///
+    ///Returns whether nick was present.
+    pub fn remove(&mut self, nick: &str) -> bool {
+        self.members.remove(&Roster::key(nick)).is_some()
+    }
+
+    ///Renames member on NICK.
+    pub fn rename(&mut self, old: &str, new: &str) {
+        if let Some(mut member) = self.members.remove(&Roster::key(old)) {
+            if let Some(hostmask) = member.hostmask.take() {
+                member.hostmask = Some(format!("{}{}", new, &hostmask[hostmask.find('!').unwrap_or(hostmask.len())..]));
+            }
+            member.nick = new.to_string();
+            self.members.insert(Roster::key(new), member);
+        }
+    }
+
+    ///Applies channel MODE e.g. `+ov-v` with its arguments.
+    pub fn mode(&mut self, modes: &str, args: &[&str]) {
+        let mut args = args.iter();
+        let mut is_set = true;
+
+        for mode in modes.chars() {
+            match mode {
+                '+' => is_set = true,
+                '-' => is_set = false,
+                //Modes with argument which are not about privileges.
+                'b' | 'e' | 'I' | 'k' => { args.next(); },
+                'l' if is_set         => { args.next(); },
+                _ => if let Some(prefix) = mode_prefix(mode) {
+                    if let Some(member) = args.next().and_then(|nick| self.members.get_mut(&Roster::key(nick))) {
+                        member.set_prefix(prefix, is_set);
+                    }
+                },
+            }
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, nick: &str) -> Option<&Member> {
+        self.members.get(&Roster::key(nick))
+    }
+
+    #[inline]
+    pub fn is_op(&self, nick: &str) -> bool {
+        self.get(nick).map_or(false, |member| member.is_op())
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    #[inline(always)]
+    pub fn clear(&mut self) {
+        self.members.clear();
+        self.names = None;
+    }
+
+    ///Returns members sorted by privileges and then by nickname.
+    pub fn members(&self) -> Vec<&Member> {
+        let rank = |member: &Member| member.prefix().chars().next().and_then(|ch| PREFIXES.find(ch)).unwrap_or(PREFIXES.len());
+        let mut members: Vec<&Member> = self.members.values().collect();
+        members.sort_by(|left, right| (rank(*left), left.nick.to_lowercase()).cmp(&(rank(*right), right.nick.to_lowercase())));
+        members
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roster_names() {
+        let mut roster = Roster::new();
+        roster.join("Gone!gone@irc.net", 100);
+
+        roster.names("@Douman +Kuu");
+        roster.names("Someone!some@irc.net @+Voiced");
+        roster.end_names();
+
+        assert!(roster.len() == 4);
+        assert!(roster.get("gone").is_none());
+        assert!(roster.is_op("douman"));
+        assert!(!roster.is_op("Kuu"));
+        assert!(roster.get("Kuu").unwrap().prefix() == "+");
+        assert!(roster.get("Voiced").unwrap().prefixes == "@+");
+        assert!(roster.get("Someone").unwrap().hostmask == Some("Someone!some@irc.net".to_string()));
+
+        let nicks: Vec<&str> = roster.members().iter().map(|member| &member.nick[..]).collect();
+        assert!(nicks == vec!["Douman", "Voiced", "Kuu", "Someone"]);
+    }
+
+    #[test]
+    fn test_roster_events() {
+        let mut roster = Roster::new();
+
+        roster.join("Douman!douman@irc.net", 100);
+        roster.join("Kuu!kuu@irc.net", 200);
+        assert!(roster.get("Douman").unwrap().joined == Some(100));
+
+        roster.mode("+ov-b+l", &["Douman", "Kuu", "*!*@*.spam", "10"]);
+        assert!(roster.is_op("Douman"));
+        assert!(roster.get("Kuu").unwrap().prefix() == "+");
+
+        roster.mode("-o", &["Douman"]);
+        assert!(!roster.is_op("Douman"));
+
+        roster.rename("Douman", "DoumanAsh");
+        assert!(roster.get("Douman").is_none());
+        let member = roster.get("doumanash").unwrap();
+        assert!(member.nick == "DoumanAsh");
+        assert!(member.hostmask == Some("DoumanAsh!douman@irc.net".to_string()));
+        assert!(member.joined == Some(100));
+
+        assert!(roster.remove("Kuu"));
+        assert!(!roster.remove("Kuu"));
+        assert!(roster.len() == 1);
+    }
+}
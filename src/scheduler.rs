@@ -0,0 +1,319 @@
+//! Reminders and timers
+
+extern crate time;
+
+use std::sync::{Arc, Mutex, Condvar};
+use std::time::Duration;
+use std::thread;
+use std::fmt;
+
+use utils;
+
+///Reminder to be sent at specified time.
+#[derive(Clone, Debug)]
+pub struct Reminder {
+    pub id: u64,
+    pub nick: String,
+    pub channel: String,
+    ///Time in seconds when reminder is due.
+    pub time: i64,
+    pub text: String
+}
+
+impl Reminder {
+    ///Parses line of state file.
+    fn parse(line: &str) -> Option<Reminder> {
+        let parts: Vec<&str> = line.splitn(5, '\t').collect();
+        if parts.len() < 5 {
+            return None;
+        }
+
+        match (parts[0].parse::<u64>(), parts[3].parse::<i64>()) {
+            (Ok(id), Ok(time)) => Some(Reminder {
+                id: id,
+                nick: parts[1].to_string(),
+                channel: parts[2].to_string(),
+                time: time,
+                text: parts[4].to_string()
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Reminder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}\t{}\t{}\t{}\t{}", self.id, self.nick, self.channel, self.time, utils::state_field(&self.text))
+    }
+}
+
+///Parses absolute local time `HH:MM` or `YYYY-MM-DD HH:MM` into seconds.
+///
+///Time without date is the nearest one after `now` i.e. either today or tomorrow.
+pub fn parse_at(date: Option<&str>, clock: &str, now: i64) -> Option<i64> {
+    let mut tm = match date {
+        Some(date) => match time::strptime(&format!("{} {}", date, clock), "%Y-%m-%d %H:%M") {
+            Ok(mut tm) => {
+                tm.tm_utcoff = time::now().tm_utcoff;
+                tm
+            },
+            Err(_) => return None,
+        },
+        None => match time::strptime(clock, "%H:%M") {
+            Ok(clock) => {
+                let mut tm = time::at(time::Timespec::new(now, 0));
+                tm.tm_hour = clock.tm_hour;
+                tm.tm_min = clock.tm_min;
+                tm.tm_sec = 0;
+                tm.tm_nsec = 0;
+                tm
+            },
+            Err(_) => return None,
+        },
+    };
+    //Let mktime() figure out whether DST is in effect at that time.
+    tm.tm_isdst = -1;
+
+    let mut result = tm.to_timespec().sec;
+    if date.is_none() && result <= now {
+        //Day across DST change is not 86400 seconds long, so let mktime() normalize the next day.
+        tm.tm_mday += 1;
+        tm.tm_isdst = -1;
+        result = tm.to_timespec().sec;
+    }
+
+    Some(result)
+}
+
+///Persistent queue of reminders ordered by time.
+pub struct Scheduler {
+    path: String,
+    inner: Vec<Reminder>,
+    next_id: u64,
+    ///Max number of pending reminders of one user.
+    limit: usize
+}
+
+impl Scheduler {
+    ///Loads reminders from file, if it exists.
+    pub fn open(path: &str, limit: usize) -> Scheduler {
+        let mut inner = utils::load_state(path, Reminder::parse);
+        inner.sort_by(|left: &Reminder, right: &Reminder| left.time.cmp(&right.time));
+
+        Scheduler {
+            path: path.to_string(),
+            next_id: inner.iter().map(|reminder| reminder.id).max().unwrap_or(0) + 1,
+            inner: inner,
+            limit: limit
+        }
+    }
+
+    #[inline(always)]
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+    }
+
+    ///Schedules reminder.
+    ///
+    ///Returns its id or error if user has too many pending reminders.
+    pub fn add(&mut self, nick: &str, channel: &str, time: i64, text: &str) -> Result<u64, String> {
+        if self.list(nick).len() >= self.limit {
+            return Err(format!("you already have {} pending reminders", self.limit));
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let pos = self.inner.iter().position(|reminder| reminder.time > time).unwrap_or(self.inner.len());
+        self.inner.insert(pos, Reminder {
+            id: id,
+            nick: nick.to_string(),
+            channel: channel.to_string(),
+            time: time,
+            text: text.to_string()
+        });
+        self.save();
+
+        Ok(id)
+    }
+
+    ///Returns pending reminders of user.
+    pub fn list(&self, nick: &str) -> Vec<&Reminder> {
+        let nick = nick.to_lowercase();
+        self.inner.iter().filter(|reminder| reminder.nick.to_lowercase() == nick).collect()
+    }
+
+    ///Deletes user's reminder.
+    ///
+    ///Returns false if there is no such reminder.
+    pub fn delete(&mut self, nick: &str, id: u64) -> bool {
+        let nick = nick.to_lowercase();
+        match self.inner.iter().position(|reminder| reminder.id == id && reminder.nick.to_lowercase() == nick) {
+            Some(idx) => {
+                self.inner.remove(idx);
+                self.save();
+                true
+            },
+            None => false,
+        }
+    }
+
+    ///Removes and returns reminders which are due at `now`.
+    pub fn due(&mut self, now: i64) -> Vec<Reminder> {
+        let len = self.inner.iter().take_while(|reminder| reminder.time <= now).count();
+        if len == 0 {
+            return Vec::new();
+        }
+
+        let result = self.inner.drain(..len).collect();
+        self.save();
+        result
+    }
+
+    #[inline]
+    ///Returns time of the nearest reminder.
+    pub fn next_time(&self) -> Option<i64> {
+        self.inner.first().map(|reminder| reminder.time)
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    ///Writes pending reminders to file ordered by due time.
+    pub fn save(&self) {
+        utils::save_state(&self.path, self.inner.iter());
+    }
+}
+
+///Wakes main loop up at specified time.
+///
+///Main loop is blocked on reading from server, so the only way to wake it up is to make server send something.
+#[derive(Clone)]
+pub struct Waker {
+    inner: Arc<(Mutex<Option<i64>>, Condvar)>
+}
+
+impl Waker {
+    #[inline(always)]
+    pub fn new() -> Waker {
+        Waker {
+            inner: Arc::new((Mutex::new(None), Condvar::new()))
+        }
+    }
+
+    ///Sets time(in seconds) to wake up at.
+    pub fn set(&self, time: Option<i64>) {
+        let &(ref wake_at, ref cvar) = &*self.inner;
+        *wake_at.lock().unwrap() = time;
+        cvar.notify_all();
+    }
+
+    ///Starts thread which calls `wake` at requested time.
+    pub fn start<F: Fn() + Send + 'static>(&self, wake: F) -> thread::JoinHandle<()> {
+        let inner = self.inner.clone();
+
+        thread::spawn(move || {
+            let &(ref wake_at, ref cvar) = &*inner;
+            let mut wake_at = wake_at.lock().unwrap();
+            loop {
+                match *wake_at {
+                    Some(time) => {
+                        let left = time - time::get_time().sec;
+                        if left <= 0 {
+                            *wake_at = None;
+                            wake();
+                        }
+                        else {
+                            wake_at = cvar.wait_timeout(wake_at, Duration::from_millis(left as u64 * 1000)).unwrap().0;
+                        }
+                    },
+                    None => wake_at = cvar.wait(wake_at).unwrap(),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate time;
+    use super::*;
+    use std;
+
+    #[test]
+    fn test_scheduler() {
+        let path = "test_reminders.txt";
+        {
+            let mut scheduler = Scheduler::open(path, 2);
+            assert!(scheduler.next_time().is_none());
+
+            assert!(scheduler.add("Douman", "#vndis", 300, "third") == Ok(1));
+            assert!(scheduler.add("Kuu", "#vndis", 100, "first") == Ok(2));
+            assert!(scheduler.add("douman", "#vndis", 200, "second") == Ok(3));
+            assert!(scheduler.add("Douman", "#vndis", 400, "too much").is_err());
+            assert!(scheduler.next_time() == Some(100));
+
+            assert!(scheduler.list("DOUMAN").len() == 2);
+            assert!(!scheduler.delete("Kuu", 1));
+            assert!(scheduler.delete("Douman", 1));
+        }
+
+        let mut scheduler = Scheduler::open(path, 2);
+        assert!(scheduler.len() == 2);
+        assert!(scheduler.due(50).is_empty());
+
+        let due = scheduler.due(200);
+        assert!(due.len() == 2);
+        assert!(due[0].text == "first");
+        assert!(due[1].text == "second");
+        assert!(scheduler.len() == 0);
+        assert!(scheduler.add("Kuu", "#vndis", 500, "new") == Ok(4));
+
+        std::fs::remove_file(path).unwrap_or(());
+    }
+
+    #[test]
+    fn test_parse_at() {
+        let now = time::get_time().sec;
+
+        let at = parse_at(None, "10:00", now).unwrap();
+        assert!(at > now && at <= now + 86400 + 3600);
+        let tm = time::at(time::Timespec::new(at, 0));
+        assert!(tm.tm_hour == 10 && tm.tm_min == 0);
+
+        //Passed time is moved to tomorrow at the same local clock, including days of DST change.
+        for day in 0..366 {
+            let mut noon = time::at(time::Timespec::new(now + day * 86400, 0));
+            noon.tm_hour = 12;
+            noon.tm_min = 0;
+            noon.tm_sec = 30;
+            noon.tm_isdst = -1;
+            let noon = noon.to_timespec().sec;
+
+            let at = parse_at(None, "12:00", noon).unwrap();
+            let tm = time::at(time::Timespec::new(at, 0));
+            assert!(tm.tm_hour == 12 && tm.tm_min == 0 && tm.tm_sec == 0);
+            assert!(at > noon && at < noon + 86400 + 3600);
+        }
+
+        let at = parse_at(Some("2030-01-02"), "18:30", now).unwrap();
+        let tm = time::at(time::Timespec::new(at, 0));
+        assert!(tm.tm_year == 130 && tm.tm_mon == 0 && tm.tm_mday == 2);
+        assert!(tm.tm_hour == 18 && tm.tm_min == 30);
+
+        assert!(parse_at(None, "nya", now).is_none());
+        assert!(parse_at(Some("2030/01/02"), "18:30", now).is_none());
+    }
+
+    #[test]
+    fn test_waker() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let waker = Waker::new();
+        waker.start(move || sender.send(()).unwrap());
+
+        waker.set(Some(0));
+        assert!(receiver.recv().is_ok());
+    }
+}
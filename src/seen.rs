@@ -0,0 +1,266 @@
+//! Last activity of users
+
+use std::collections::HashMap;
+use std::fmt;
+
+use log;
+use utils;
+
+///Number of updates after which index is written to file.
+const SAVE_EVERY: usize = 100;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Event {
+    Message,
+    Join,
+    Part,
+    Quit
+}
+
+impl Event {
+    fn parse(name: &str) -> Option<Event> {
+        match name {
+            "message" => Some(Event::Message),
+            "join"    => Some(Event::Join),
+            "part"    => Some(Event::Part),
+            "quit"    => Some(Event::Quit),
+            _         => None,
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.write_str(match *self {
+            Event::Message => "message",
+            Event::Join    => "join",
+            Event::Part    => "part",
+            Event::Quit    => "quit",
+        })
+    }
+}
+
+///Last activity of user.
+#[derive(Clone, Debug)]
+pub struct Seen {
+    pub nick: String,
+    pub event: Event,
+    ///Time in seconds.
+    pub time: i64,
+    ///Channel of event which is empty for QUIT.
+    pub channel: String,
+    ///Time and text of last message.
+    pub message: Option<(i64, String)>
+}
+
+impl Seen {
+    ///Parses line of index file.
+    fn parse(line: &str) -> Option<Seen> {
+        let parts: Vec<&str> = line.splitn(6, '\t').collect();
+        if parts.len() < 4 {
+            return None;
+        }
+
+        let event = Event::parse(parts[1]);
+        let time = parts[2].parse::<i64>().ok();
+        let message = match (parts.get(4).and_then(|time| time.parse::<i64>().ok()), parts.get(5)) {
+            (Some(time), Some(text)) => Some((time, text.to_string())),
+            _                        => None,
+        };
+
+        match (event, time) {
+            (Some(event), Some(time)) => Some(Seen {
+                nick: parts[0].to_string(),
+                event: event,
+                time: time,
+                channel: parts[3].to_string(),
+                message: message
+            }),
+            _ => None,
+        }
+    }
+
+    ///Describes activity relatively to `now`.
+    pub fn describe(&self, now: i64) -> String {
+        let ago = format_duration(now - self.time);
+        let mut result = match self.event {
+            Event::Message => format!("{} was last seen {} ago on {}", self.nick, ago, self.channel),
+            Event::Join    => format!("{} was last seen {} ago joining {}", self.nick, ago, self.channel),
+            Event::Part    => format!("{} was last seen {} ago leaving {}", self.nick, ago, self.channel),
+            Event::Quit    => format!("{} was last seen {} ago quitting", self.nick, ago),
+        };
+
+        if let Some((time, ref text)) = self.message {
+            result.push_str(&format!(". Last message {} ago: {}", format_duration(now - time), text));
+        }
+
+        result
+    }
+}
+
+impl fmt::Display for Seen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        try!(write!(f, "{}\t{}\t{}\t{}", self.nick, self.event, self.time, self.channel));
+        if let Some((time, ref text)) = self.message {
+            try!(write!(f, "\t{}\t{}", time, utils::state_field(text)));
+        }
+        Ok(())
+    }
+}
+
+///Formats duration in seconds by two largest units e.g. `2h 5m`.
+pub fn format_duration(secs: i64) -> String {
+    const UNITS: [(i64, &'static str); 4] = [(86400, "d"), (3600, "h"), (60, "m"), (1, "s")];
+    let mut secs = if secs < 0 { 0 } else { secs };
+    let mut parts = Vec::new();
+
+    for &(size, name) in UNITS.iter() {
+        if secs >= size || (size == 1 && parts.is_empty()) {
+            parts.push(format!("{}{}", secs / size, name));
+            secs %= size;
+        }
+        else if !parts.is_empty() {
+            break;
+        }
+
+        if parts.len() == 2 {
+            break;
+        }
+    }
+
+    parts.join(" ")
+}
+
+///Persistent index of users' last activity.
+pub struct SeenIndex {
+    path: String,
+    inner: HashMap<String, Seen>,
+    ///Number of updates since last save.
+    unsaved: usize
+}
+
+impl SeenIndex {
+    ///Loads index from file, if it exists.
+    pub fn open(path: &str) -> SeenIndex {
+        SeenIndex {
+            path: path.to_string(),
+            inner: utils::load_state(path, Seen::parse).into_iter().map(|seen| (seen.nick.to_lowercase(), seen)).collect(),
+            unsaved: 0
+        }
+    }
+
+    ///Records user's activity.
+    pub fn update(&mut self, nick: &str, event: Event, channel: &str, text: Option<&str>, now: i64) {
+        {
+            let seen = self.inner.entry(nick.to_lowercase()).or_insert(Seen {
+                nick: nick.to_string(),
+                event: event,
+                time: now,
+                channel: channel.to_string(),
+                message: None
+            });
+
+            //Entries imported from log can be older than what is already known.
+            if seen.time > now {
+                return;
+            }
+
+            seen.nick = nick.to_string();
+            seen.event = event;
+            seen.time = now;
+            seen.channel = channel.to_string();
+            if let Some(text) = text {
+                seen.message = Some((now, text.to_string()));
+            }
+        }
+
+        self.unsaved += 1;
+        if self.unsaved >= SAVE_EVERY {
+            self.save();
+        }
+    }
+
+    ///Fills index from log history, both file and in-memory buffer.
+    pub fn import(&mut self, channel: &str, log: &mut log::IrcLog) {
+        for entry in log.get_entries(&log::FilterLog::None) {
+            let time = entry.timestamp();
+            match entry.kind() {
+                log::Kind::Message | log::Kind::Action => self.update(entry.nickname(), Event::Message, channel, Some(&entry.message()[..]), time),
+                log::Kind::Join    => self.update(entry.nickname(), Event::Join, channel, None, time),
+                log::Kind::Part    => self.update(entry.nickname(), Event::Part, channel, None, time),
+                log::Kind::Quit    => self.update(entry.nickname(), Event::Quit, "", None, time),
+                _                  => (),
+            }
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, nick: &str) -> Option<&Seen> {
+        self.inner.get(&nick.to_lowercase())
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    ///Writes index to file.
+    ///
+    ///Each line is last event of one user with its time and channel, then time and text of last message, if any.
+    pub fn save(&mut self) {
+        if utils::save_state(&self.path, self.inner.values()) {
+            self.unsaved = 0;
+        }
+    }
+}
+
+impl Drop for SeenIndex {
+    fn drop(&mut self) {
+        if self.unsaved > 0 {
+            self.save();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std;
+
+    #[test]
+    fn test_format_duration() {
+        assert!(format_duration(0) == "0s");
+        assert!(format_duration(-5) == "0s");
+        assert!(format_duration(59) == "59s");
+        assert!(format_duration(61) == "1m 1s");
+        assert!(format_duration(7500) == "2h 5m");
+        assert!(format_duration(90000) == "1d 1h");
+        assert!(format_duration(86401) == "1d");
+    }
+
+    #[test]
+    fn test_seen_index() {
+        let path = "test_seen.idx";
+        {
+            let mut index = SeenIndex::open(path);
+            assert!(index.is_empty());
+
+            index.update("Douman", Event::Join, "#vndis", None, 100);
+            index.update("Douman", Event::Message, "#vndis", Some("tadaima\tnya"), 200);
+            index.update("Douman", Event::Quit, "", None, 300);
+            //Older event is ignored
+            index.update("douman", Event::Join, "#vndis", None, 50);
+
+            let seen = index.get("DOUMAN").unwrap();
+            assert!(seen.event == Event::Quit);
+            assert!(seen.describe(7500) == "Douman was last seen 2h ago quitting. Last message 2h 1m ago: tadaima\tnya");
+        }
+
+        let index = SeenIndex::open(path);
+        let seen = index.get("douman").unwrap();
+        assert!(seen.time == 300);
+        assert!(seen.message == Some((200, "tadaima nya".to_string())));
+
+        std::fs::remove_file(path).unwrap_or(());
+    }
+}
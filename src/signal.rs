@@ -0,0 +1,153 @@
+//! Process signals handling
+
+extern crate libc;
+
+use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+use std::time::Duration;
+use std::thread;
+use std;
+
+static STOP: AtomicBool = ATOMIC_BOOL_INIT;
+static RELOAD: AtomicBool = ATOMIC_BOOL_INIT;
+
+const SIGNALS: [libc::c_int; 3] = [libc::SIGTERM, libc::SIGINT, libc::SIGHUP];
+///Interval in milliseconds of checking for received signals.
+const WATCH_INTERVAL_MS: u64 = 200;
+
+extern "C" fn handler(signal: libc::c_int) {
+    match signal {
+        libc::SIGHUP => RELOAD.store(true, Ordering::SeqCst),
+        _            => STOP.store(true, Ordering::SeqCst),
+    }
+}
+
+///Installs handlers of SIGTERM, SIGINT(stop) and SIGHUP(reload).
+pub fn init() {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        //std retries reads interrupted by signal anyway, so main loop is woken up by watch().
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+
+        for signal in SIGNALS.iter() {
+            if libc::sigaction(*signal, &action, std::ptr::null_mut()) != 0 {
+                println!(">>>ERROR: Unable to set handler of signal {}", signal);
+            }
+        }
+    }
+}
+
+///Blocks or unblocks handled signals for current thread.
+fn block(is_block: bool) {
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        for signal in SIGNALS.iter() {
+            libc::sigaddset(&mut set, *signal);
+        }
+
+        libc::pthread_sigmask(if is_block { libc::SIG_BLOCK } else { libc::SIG_UNBLOCK }, &set, std::ptr::null_mut());
+    }
+}
+
+///Runs `spawn` with handled signals blocked.
+///
+///Threads spawned meanwhile inherit it, so that only main thread is interrupted by signals.
+pub fn spawn_blocked<T, F: FnOnce() -> T>(spawn: F) -> T {
+    block(true);
+    let result = spawn();
+    block(false);
+    result
+}
+
+#[inline(always)]
+///Returns whether stop or reload is requested, but not checked yet.
+pub fn is_pending() -> bool {
+    STOP.load(Ordering::SeqCst) || RELOAD.load(Ordering::SeqCst)
+}
+
+///Starts thread which calls `wake` once signal is received.
+///
+///Main loop is blocked on reading from server, so it notices signal only after something is received.
+pub fn watch<F: Fn() + Send + 'static>(wake: F) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut is_woken = false;
+        loop {
+            thread::sleep(Duration::from_millis(WATCH_INTERVAL_MS));
+
+            let is_pending = is_pending();
+            if is_pending && !is_woken {
+                wake();
+            }
+            is_woken = is_pending;
+        }
+    })
+}
+
+///Sleeps for `delay_ms` milliseconds unless signal is received meanwhile.
+///
+///Returns false if sleep is interrupted by signal.
+pub fn sleep(delay_ms: u64) -> bool {
+    let mut left = delay_ms;
+    while left > 0 {
+        if is_pending() {
+            return false;
+        }
+
+        let step = std::cmp::min(left, WATCH_INTERVAL_MS);
+        thread::sleep(Duration::from_millis(step));
+        left -= step;
+    }
+
+    !is_pending()
+}
+
+#[inline(always)]
+///Returns whether stop is requested since last check.
+pub fn is_stop() -> bool {
+    STOP.swap(false, Ordering::SeqCst)
+}
+
+#[inline(always)]
+///Returns whether reload is requested since last check.
+pub fn is_reload() -> bool {
+    RELOAD.swap(false, Ordering::SeqCst)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate libc;
+    extern crate time;
+    use super::*;
+    use super::handler;
+    use std;
+
+    //Handler is called directly, as real signal would hit every test in process.
+    #[test]
+    fn test_signals() {
+        assert!(!is_stop());
+        assert!(!is_reload());
+        assert!(!is_pending());
+        assert!(sleep(10));
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        watch(move || sender.send(()).unwrap_or(()));
+
+        handler(libc::SIGHUP);
+        assert!(receiver.recv().is_ok());
+        assert!(is_pending());
+        let start = time::precise_time_s();
+        assert!(!sleep(60000));
+        assert!(time::precise_time_s() - start < 1.0);
+        assert!(is_reload());
+        assert!(!is_reload());
+        assert!(!is_stop());
+
+        handler(libc::SIGTERM);
+        assert!(!sleep(60000));
+        assert!(is_stop());
+        assert!(!is_stop());
+        assert!(!is_pending());
+    }
+}
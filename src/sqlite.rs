@@ -0,0 +1,254 @@
+//! Log store in SQLite database
+
+extern crate rusqlite;
+
+use self::rusqlite::{Connection, Row};
+use self::rusqlite::types::ToSql;
+
+use std::collections::HashMap;
+
+use log::{self, IrcEntry, Filter, LogStore};
+
+///Database with logs of all channels.
+pub const DB_PATH: &'static str = "vndis.db";
+
+///Entries and full-text index of their messages which is filled by trigger.
+const SCHEMA: &'static str = "
+CREATE TABLE IF NOT EXISTS entries (
+    id       INTEGER PRIMARY KEY,
+    time     INTEGER NOT NULL,
+    utcoff   INTEGER NOT NULL,
+    channel  TEXT NOT NULL,
+    kind     TEXT NOT NULL,
+    nick     TEXT NOT NULL,
+    hostmask TEXT,
+    message  TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS entries_time ON entries(channel, time);
+CREATE VIRTUAL TABLE IF NOT EXISTS entries_fts USING fts5(message, content='entries', content_rowid='id');
+CREATE TRIGGER IF NOT EXISTS entries_insert AFTER INSERT ON entries BEGIN
+    INSERT INTO entries_fts(rowid, message) VALUES (new.id, new.message);
+END;
+";
+
+const COLUMNS: &'static str = "entries.time, entries.utcoff, entries.channel, entries.kind, entries.nick, entries.hostmask, entries.message";
+
+///Builds FTS5 query which matches all words as quoted strings, so that user cannot break its syntax.
+///
+///Words are prefixes, so that search is close to substring one of log files.
+fn fts_query(text: &str) -> String {
+    log::search_words(text).iter()
+                           .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+                           .collect::<Vec<_>>()
+                           .join(" ")
+}
+
+///Creates entry from row of `COLUMNS`.
+///
+///Row of unknown kind is skipped.
+fn entry_from_row(row: &Row) -> rusqlite::Result<Option<IrcEntry>> {
+    let kind: String = try!(row.get(3));
+    let kind = match log::Kind::parse(&kind) {
+        Some(kind) => kind,
+        None       => return Ok(None),
+    };
+
+    let mut entry = IrcEntry::with_kind(kind, try!(row.get(4)), try!(row.get(6)));
+    entry.set_time(try!(row.get(0)), try!(row.get(1)));
+    entry.set_channel(try!(row.get(2)));
+    entry.set_hostmask(try!(row.get(5)));
+    Ok(Some(entry))
+}
+
+///Log of one channel in SQLite database.
+///
+///Messages are indexed by FTS5, so that search matches beginnings of words.
+pub struct SqliteStore {
+    conn: Connection,
+    path: String,
+    channel: String
+}
+
+impl SqliteStore {
+    ///Opens database, creating it if needed.
+    pub fn open(path: &str, channel: &str) -> Result<SqliteStore, String> {
+        let conn = try!(Connection::open(path).map_err(|err| format!("Unable to open {}: {}", path, err)));
+        try!(conn.execute_batch(SCHEMA).map_err(|err| format!("Unable to create tables in {}: {}", path, err)));
+
+        Ok(SqliteStore {
+            conn: conn,
+            path: path.to_string(),
+            channel: channel.to_string()
+        })
+    }
+
+    ///Runs query over `COLUMNS` and returns entries in order of rows.
+    fn query(&self, sql: &str, params: &[&ToSql]) -> Vec<IrcEntry> {
+        let result = self.conn.prepare(sql).and_then(|mut stmt| {
+            let entries: Vec<IrcEntry> = try!(stmt.query_map(params, |row| entry_from_row(row))).filter_map(|row| row.ok())
+                                                                                                   .filter_map(|entry| entry)
+                                                                                                   .collect();
+            Ok(entries)
+        });
+
+        match result {
+            Ok(entries) => entries,
+            Err(err)    => {
+                println!(">>>ERROR: Unable to read {}: {}", &self.path, err);
+                Vec::new()
+            },
+        }
+    }
+
+    ///Imports entries which are not in database yet.
+    ///
+    ///Entries are the same if they have equal time, UTC offset, kind, nick and message.
+    ///Such entries may repeat within one second, so entry is imported only if database has fewer of them.
+    ///Thus import can be repeated at any time without duplicating entries.
+    ///Returns number of imported entries.
+    pub fn import(&mut self, entries: Vec<IrcEntry>) -> usize {
+        let mut seen = HashMap::new();
+        let mut missing = Vec::new();
+
+        for entry in entries {
+            let num = {
+                let key = (entry.timestamp(), entry.utcoff(), entry.kind().name(), entry.nickname().clone(), entry.message().clone());
+                let num = seen.entry(key).or_insert(0);
+                *num += 1;
+                *num
+            };
+
+            let stored: rusqlite::Result<i64> = self.conn.prepare_cached("SELECT COUNT(*) FROM entries WHERE channel = ? AND time = ? AND utcoff = ? AND kind = ? AND nick = ? AND message = ?").and_then(|mut stmt| {
+                let params: [&ToSql; 6] = [&self.channel, &entry.timestamp(), &entry.utcoff(), &entry.kind().name(), entry.nickname(), entry.message()];
+                stmt.query_row(&params[..], |row| row.get(0))
+            });
+
+            match stored {
+                Ok(stored) => if stored < num {
+                    missing.push(entry);
+                },
+                Err(err)   => {
+                    println!(">>>ERROR: Unable to read {}: {}", &self.path, err);
+                    return 0;
+                },
+            }
+        }
+
+        self.append(&missing);
+        missing.len()
+    }
+}
+
+impl LogStore for SqliteStore {
+    ///Appends entries in one transaction.
+    fn append(&mut self, entries: &[IrcEntry]) {
+        let channel = &self.channel;
+        let result = self.conn.transaction().and_then(|tx| {
+            {
+                let mut stmt = try!(tx.prepare("INSERT INTO entries (time, utcoff, channel, kind, nick, hostmask, message) VALUES (?, ?, ?, ?, ?, ?, ?)"));
+                for entry in entries {
+                    let hostmask = entry.hostmask().cloned();
+                    let params: [&ToSql; 7] = [&entry.timestamp(), &entry.utcoff(), channel, &entry.kind().name(),
+                                               entry.nickname(), &hostmask, entry.message()];
+                    try!(stmt.execute(&params[..]));
+                }
+            }
+            tx.commit()
+        });
+
+        if let Err(err) = result {
+            println!(">>>ERROR: Unable to write {}: {}", &self.path, err);
+        }
+    }
+
+    fn range(&mut self, filter: &Filter) -> Vec<IrcEntry> {
+        let sql = format!("SELECT {} FROM entries WHERE channel = ? AND time > ? ORDER BY time, id", COLUMNS);
+        let since = filter.since().unwrap_or(i64::min_value());
+
+        self.query(&sql, &[&self.channel, &since]).into_iter().filter(|entry| filter.accepts(entry)).collect()
+    }
+
+    ///Matches words of `text` by full-text index.
+    fn search(&mut self, text: &str, limit: usize) -> Vec<IrcEntry> {
+        let query = fts_query(text);
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        let sql = format!("SELECT {} FROM entries_fts JOIN entries ON entries.id = entries_fts.rowid \
+                           WHERE entries_fts MATCH ? AND entries.channel = ? ORDER BY entries.time DESC, entries.id DESC LIMIT ?", COLUMNS);
+        let mut result = self.query(&sql, &[&query, &self.channel, &(limit as i64)]);
+        result.reverse();
+        result
+    }
+
+    fn last(&mut self, num: usize) -> Vec<IrcEntry> {
+        let sql = format!("SELECT {} FROM entries WHERE channel = ? ORDER BY time DESC, id DESC LIMIT ?", COLUMNS);
+        let mut result = self.query(&sql, &[&self.channel, &(num as i64)]);
+        result.reverse();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::{IrcEntry, Kind, FilterLog, KindFilter, LogStore};
+    use std;
+
+    fn entry(time: i64, kind: Kind, message: &str) -> IrcEntry {
+        let mut entry = IrcEntry::with_kind(kind, "Kuu".to_string(), message.to_string());
+        entry.set_time(time, 0);
+        entry
+    }
+
+    #[test]
+    fn test_fts_query() {
+        assert!(fts_query("Nya  nya") == "\"nya\"* \"nya\"*");
+        assert!(fts_query("say \"hi\" OR") == "\"say\"* \"\"\"hi\"\"\"* \"or\"*");
+        assert!(fts_query(" ").is_empty());
+    }
+
+    #[test]
+    fn test_sqlite_store() {
+        std::env::set_current_dir(std::env::current_exe().unwrap().parent().unwrap())
+                  .unwrap_or_else(|err| panic!("cannot enter my own directory :(. Err={}", err));
+
+        let path = "test_store.db";
+        {
+            let mut store = SqliteStore::open(path, "#vndis").unwrap();
+            let mut other = SqliteStore::open(path, "#other").unwrap();
+            store.append(&[entry(100, Kind::Message, "Tadaima, master"), entry(200, Kind::Join, "kuu@irc.net"), entry(300, Kind::Action, "says tadaima")]);
+            other.append(&[entry(150, Kind::Message, "tadaima")]);
+
+            let all = store.range(&FilterLog::None);
+            assert!(all.len() == 3);
+            assert!(all[0].channel() == "#vndis" && all[1].kind() == Kind::Join);
+            assert!(store.count(&(FilterLog::Last(100), KindFilter::parse(&["-join"]).unwrap())) == 1);
+
+            let found = store.search("TADAIMA", 10);
+            assert!(found.iter().map(|entry| entry.timestamp()).collect::<Vec<_>>() == vec![100, 300]);
+            assert!(store.search("tadaima", 1)[0].timestamp() == 300);
+            assert!(store.search("tada", 10).len() == 2);
+            assert!(store.search("master OR nya", 10).is_empty());
+            assert!(other.search("tadaima", 10).len() == 1);
+
+            assert!(store.last(2).iter().map(|entry| entry.timestamp()).collect::<Vec<_>>() == vec![200, 300]);
+        }
+
+        //Only entries which are not in database yet are imported.
+        let mut store = SqliteStore::open(path, "#vndis").unwrap();
+        let file = vec![entry(50, Kind::Message, "old"), entry(100, Kind::Message, "Tadaima, master"),
+                        entry(400, Kind::Message, "lol"), entry(400, Kind::Message, "lol")];
+        assert!(store.import(file.clone()) == 3);
+        assert!(store.import(file) == 0);
+
+        let all = store.range(&FilterLog::None);
+        assert!(all.len() == 6);
+        assert!(all[0].message() == "old");
+        assert!(all[4].message() == "lol" && all[5].message() == "lol");
+
+        drop(store);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -2,6 +2,8 @@ extern crate irc;
 extern crate libc;
 
 use self::libc::{c_void};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
 use std::fmt;
 use std::fmt::Write;
 
@@ -39,6 +41,150 @@ pub fn get_nick(msg_prefix: &Option<String>) -> Option<String> {
     result
 }
 
+#[inline]
+///Returns value of custom option from config.
+pub fn get_option<'a>(config: &'a irc::client::prelude::Config, name: &str) -> Option<&'a String> {
+    config.options.as_ref().and_then(|options| options.get(name))
+}
+
+#[inline]
+///Returns value of IRCv3 message tag.
+pub fn get_tag(message: &irc::client::prelude::Message, name: &str) -> Option<String> {
+    message.tags.as_ref().and_then(|tags| tags.iter().find(|tag| tag.0 == name)).and_then(|tag| tag.1.clone())
+}
+
+///Splits text into parts which fit into `max_len` bytes.
+///
+///Text is split on char boundary, preferably on whitespace.
+///Each part, except for the last one, ends with `marker`.
+pub fn split_message(text: &str, max_len: usize, marker: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut text = text;
+
+    while text.len() > max_len {
+        let mut end = max_len.saturating_sub(marker.len());
+        while end > 0 && !text.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        if end == 0 {
+            //Not even single char fits, but we need to move on.
+            end = text.char_indices().nth(1).map_or(text.len(), |(idx, _)| idx);
+        }
+
+        let (part, rest) = match text[..end].rfind(char::is_whitespace) {
+            Some(pos) if pos > 0 => (&text[..pos], text[pos..].trim_left()),
+            _                    => (&text[..end], &text[end..]),
+        };
+
+        if rest.is_empty() {
+            text = part;
+            break;
+        }
+
+        result.push(format!("{}{}", part, marker));
+        text = rest;
+    }
+
+    result.push(text.to_string());
+    result
+}
+
+///Parses duration like `2h30m` into seconds.
+///
+///Duration is sequence of `num<unit>` where unit is one of `s`, `m`, `h`, `d` or `w`.
+pub fn parse_duration(text: &str) -> Option<i64> {
+    let mut result: i64 = 0;
+    let mut num = String::new();
+
+    if text.is_empty() {
+        return None;
+    }
+
+    for ch in text.chars() {
+        if ch.is_digit(10) {
+            num.push(ch);
+            continue;
+        }
+
+        let unit = match ch {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 604800,
+            _   => return None,
+        };
+
+        match num.parse::<i64>().ok().and_then(|num| num.checked_mul(unit)).and_then(|secs| result.checked_add(secs)) {
+            Some(secs) => result = secs,
+            None       => return None,
+        }
+        num.clear();
+    }
+
+    if num.is_empty() { Some(result) } else { None }
+}
+
+///Returns text of CTCP ACTION i.e. `\x01ACTION waves\x01` => `waves`
+pub fn parse_action(text: &str) -> Option<&str> {
+    const ACTION: &'static str = "\x01ACTION";
+
+    if !text.starts_with(ACTION) {
+        return None;
+    }
+
+    let action = text[ACTION.len()..].trim_right_matches('\x01');
+    if action.is_empty() {
+        Some(action)
+    }
+    else if action.starts_with(' ') {
+        Some(&action[1..])
+    }
+    else {
+        None
+    }
+}
+
+///Loads state file where each line is one item.
+///
+///Lines which cannot be parsed are skipped and missing file has no items.
+pub fn load_state<T, F: Fn(&str) -> Option<T>>(path: &str, parse: F) -> Vec<T> {
+    match File::open(path) {
+        Ok(file) => BufReader::new(file).lines().filter_map(|line| line.ok().as_ref().and_then(|line| parse(line))).collect(),
+        Err(_)   => Vec::new(),
+    }
+}
+
+///Writes items to state file line by line.
+///
+///Items are written to temporary file which then replaces state file, so that it is never left half-written.
+///Returns false on error.
+pub fn save_state<T: fmt::Display, I: Iterator<Item=T>>(path: &str, items: I) -> bool {
+    let tmp_path = format!("{}.tmp", path);
+    let result = File::create(&tmp_path).and_then(|file| {
+        let mut file = io::BufWriter::new(file);
+        for item in items {
+            try!(io::Write::write_fmt(&mut file, format_args!("{}\n", item)));
+        }
+        io::Write::flush(&mut file)
+    }).and_then(|_| fs::rename(&tmp_path, path));
+
+    match result {
+        Ok(_)    => true,
+        Err(err) => {
+            println!(">>>ERROR: Unable to save {}: {}", path, err);
+            false
+        },
+    }
+}
+
+///Makes text suitable as tab separated field of state file.
+#[inline(always)]
+pub fn state_field(text: &str) -> String {
+    text.replace('\t', " ").replace('\n', " ")
+}
+
 extern {
     fn je_malloc_usable_size(ptr: *const c_void) -> u64;
 }
@@ -84,6 +230,82 @@ mod tests {
         assert!(!result.is_some());
     }
 
+    #[test]
+    fn test_get_tag() {
+        let message = "@account=Douman :Douman!douman@irc.net PRIVMSG #vndis :ping".parse::<super::irc::client::prelude::Message>().unwrap();
+
+        assert!(super::get_tag(&message, "account") == Some("Douman".to_string()));
+        assert!(super::get_tag(&message, "time").is_none());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert!(super::parse_duration("20m") == Some(1200));
+        assert!(super::parse_duration("2h30m") == Some(9000));
+        assert!(super::parse_duration("1w1d1h1m1s") == Some(694861));
+        assert!(super::parse_duration("0h") == Some(0));
+
+        assert!(super::parse_duration("").is_none());
+        assert!(super::parse_duration("5").is_none());
+        assert!(super::parse_duration("2h30").is_none());
+        assert!(super::parse_duration("h").is_none());
+        assert!(super::parse_duration("-20h").is_none());
+        assert!(super::parse_duration("99999999999999999999d").is_none());
+    }
+
+    #[test]
+    fn test_parse_action() {
+        assert!(super::parse_action("\x01ACTION waves\x01") == Some("waves"));
+        assert!(super::parse_action("\x01ACTION waves") == Some("waves"));
+        assert!(super::parse_action("\x01ACTION\x01") == Some(""));
+
+        assert!(super::parse_action("ACTION waves") == None);
+        assert!(super::parse_action("\x01VERSION\x01") == None);
+        assert!(super::parse_action("\x01ACTIONS\x01") == None);
+    }
+
+    #[test]
+    fn test_split_message() {
+        assert!(super::split_message("nya nya", 10, "") == vec!["nya nya"]);
+        assert!(super::split_message("", 10, "") == vec![""]);
+        assert!(super::split_message("nya nya nya", 8, "") == vec!["nya nya", "nya"]);
+        assert!(super::split_message("nyanyanyanya", 8, "") == vec!["nyanyany", "anya"]);
+        assert!(super::split_message("nya nya nya", 8, "..") == vec!["nya..", "nya nya"]);
+        assert!(super::split_message("nya nya    ", 8, "") == vec!["nya nya"]);
+
+        //Cyrillic letters are 2 bytes long.
+        let parts = super::split_message("пингпинг", 5, "");
+        assert!(parts == vec!["пи", "нг", "пи", "нг"]);
+        assert!(parts.iter().all(|part| part.len() <= 5));
+
+        let text = "ただいま ".repeat(50);
+        let parts = super::split_message(&text, 100, "…");
+        assert!(parts.iter().all(|part| part.len() <= 100));
+        assert!(parts.iter().all(|part| !part.starts_with(' ')));
+        assert!(parts.last().map_or(false, |part| !part.ends_with("…")));
+    }
+
+    #[test]
+    fn test_state_file() {
+        use std;
+
+        let path = "test_state.txt";
+        assert!(super::load_state(path, |line| Some(line.to_string())).is_empty());
+
+        let items = vec!["1\tnya".to_string(), super::state_field("2\tnya\nnya"), "garbage".to_string()];
+        assert!(super::save_state(path, items.iter()));
+        assert!(std::fs::metadata(format!("{}.tmp", path)).is_err());
+
+        let loaded = super::load_state(path, |line| line.split('\t').nth(1).map(|text| text.to_string()));
+        assert!(loaded == vec!["nya", "nya nya"]);
+
+        //State is replaced as a whole.
+        assert!(super::save_state(path, items.iter().take(1)));
+        assert!(super::load_state(path, |line| Some(line.to_string())) == vec!["1\tnya"]);
+
+        std::fs::remove_file(path).unwrap_or(());
+    }
+
     #[test]
     fn test_escape() {
         let test_str = "Line1\r\n\"Name:\"\t\"Grog\"\n".to_string();